@@ -1,16 +1,20 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(name = "Mathovi")]
 #[command(author = "Alvinosh. <alvihysa89@gmail.com>")]
 #[command(version = "0.2")]
 #[command(about = "Convert text to math PNG-s", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 #[clap(group(
     clap::ArgGroup::new("input")
         .required(true)
         .args(&["input_file", "string"]),
 ))]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
     /// Name of the input file
     #[arg(short, long, value_parser = clap::value_parser!(std::path::PathBuf), value_name = "INPUT")]
     pub input_file: Option<std::path::PathBuf>,
@@ -20,9 +24,84 @@ pub struct Cli {
     pub string: Option<String>,
 
     /// Name Of the output file
-    #[arg(short, long, value_parser = clap::value_parser!(std::path::PathBuf))]
-    pub output_file: std::path::PathBuf,
+    #[arg(short, long, required = true, value_parser = clap::value_parser!(std::path::PathBuf))]
+    pub output_file: Option<std::path::PathBuf>,
 
     #[arg(long)]
     pub open: bool,
+
+    /// File of `@function name/arity = template` declarations to load
+    #[arg(long, global = true, value_name = "FILE", value_parser = clap::value_parser!(std::path::PathBuf))]
+    pub functions: Vec<std::path::PathBuf>,
+
+    /// Names applied to their arguments like f(x) rather than multiplied,
+    /// f, g and h by default
+    #[arg(long, global = true, value_name = "NAMES", value_delimiter = ',')]
+    pub function_names: Option<Vec<String>>,
+
+    /// How to typeset variables with names longer than one letter
+    #[arg(long, value_enum, default_value_t)]
+    pub identifiers: crate::expression::IdentStyle,
+
+    /// How to typeset powers of functions such as sin(x)^2
+    #[arg(long, value_enum, default_value_t)]
+    pub function_powers: crate::expression::FuncPowerStyle,
+
+    /// Which brackets to put around matrices and vectors
+    #[arg(long, value_enum, default_value_t)]
+    pub matrices: crate::expression::MatrixStyle,
+
+    /// How to typeset multiplication
+    #[arg(long, value_enum, default_value_t)]
+    pub multiplication: crate::expression::MultStyle,
+
+    /// The decimal mark between the whole and fractional part of numbers
+    #[arg(long, value_enum, default_value_t)]
+    pub decimal_mark: crate::expression::DecimalMark,
+
+    /// How to group the digits of numbers with five or more of them
+    #[arg(long, value_enum, default_value_t)]
+    pub digit_grouping: crate::expression::DigitGrouping,
+
+    /// Keep every parenthesis written in the input, not only the needed ones
+    #[arg(long)]
+    pub keep_parens: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Rewrite .mvi files in the canonical style
+    Fmt(FmtArgs),
+    /// Report ambiguous or suspicious notation in .mvi files
+    Lint(LintArgs),
+}
+
+#[derive(Args)]
+pub struct FmtArgs {
+    /// Files to format
+    #[arg(required = true, value_parser = clap::value_parser!(std::path::PathBuf))]
+    pub files: Vec<std::path::PathBuf>,
+
+    /// Only check that the files are formatted, without writing them
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    /// Files to lint
+    #[arg(required = true, value_parser = clap::value_parser!(std::path::PathBuf))]
+    pub files: Vec<std::path::PathBuf>,
+
+    /// Turn off a rule, by code or name
+    #[arg(long, value_name = "RULE")]
+    pub allow: Vec<String>,
+
+    /// Report a rule as a warning
+    #[arg(long, value_name = "RULE")]
+    pub warn: Vec<String>,
+
+    /// Report a rule as an error, failing the run
+    #[arg(long, value_name = "RULE")]
+    pub deny: Vec<String>,
 }
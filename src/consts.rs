@@ -1,4 +1,4 @@
-pub const PREFIX: &'static str = r#"
+pub const PREFIX: &str = r#"
 \documentclass{article}
 
 \pagestyle{empty}
@@ -7,11 +7,12 @@ pub const PREFIX: &'static str = r#"
 
 \usepackage[utf8]{inputenc}
 \usepackage{lmodern}
+\usepackage{amsmath}
 \usepackage{amssymb}
 
 \begin{document}
 "#;
 
-pub const SUFFIX: &'static str = r#"
+pub const SUFFIX: &str = r#"
 \end{document}
 "#;
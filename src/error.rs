@@ -1,3 +1,4 @@
+use crate::functions::Arity;
 use crate::lexer::{Token, TokenKind};
 
 #[derive(thiserror::Error, Debug)]
@@ -17,15 +18,78 @@ pub enum Error {
         found: Token,
     },
 
-    #[error("ERROR at {}:{} : Unexpected Identifier \"{}\"", line, col, ident)]
-    UnexpectedIdent {
+    #[error("ERROR : Unexpected End Of File")]
+    UnexpectedEOF,
+
+    #[error(
+        "ERROR at {}:{} : Unexpected Number Of Argumentns, Expected  \"{}\", Found  \"{}\"",
+        line,
+        col,
+        print_vec_counts(expected),
+        found
+    )]
+    WrongArgumentsOneOf {
         line: usize,
         col: usize,
-        ident: String,
+        found: usize,
+        expected: Vec<usize>,
     },
 
-    #[error("ERROR : Unexpected End Of File")]
-    UnexpectedEOF,
+    #[error(
+        "ERROR at {}:{} : Derivative Order Must Be A Positive Integer",
+        line,
+        col
+    )]
+    InvalidOrder { line: usize, col: usize },
+
+    #[error(
+        "ERROR at {}:{} : Row {} Of The Matrix Has {} Entries, Expected {}",
+        line,
+        col,
+        row,
+        found,
+        expected
+    )]
+    MatrixShape {
+        line: usize,
+        col: usize,
+        row: usize,
+        found: usize,
+        expected: usize,
+    },
+
+    #[error("ERROR at {}:{} : Unterminated {}", line, col, what)]
+    Unterminated {
+        line: usize,
+        col: usize,
+        what: &'static str,
+    },
+
+    #[error("ERROR at {}:{} : Invalid Number \"{}\"", line, col, text)]
+    InvalidNumber {
+        line: usize,
+        col: usize,
+        text: String,
+    },
+
+    #[error("ERROR at {}:{} : Unexpected Character \"{}\"", line, col, found)]
+    UnexpectedChar {
+        line: usize,
+        col: usize,
+        found: char,
+    },
+
+    #[error("ERROR at {} : Invalid Function Declaration, {}", line, message)]
+    InvalidDeclaration { line: usize, message: String },
+
+    #[error("ERROR : {} File(s) Are Not Formatted", .0)]
+    Unformatted(usize),
+
+    #[error("ERROR : Unknown Lint Rule \"{}\"", .0)]
+    UnknownLintRule(String),
+
+    #[error("ERROR : Linting Failed With {} Error(s)", .0)]
+    LintFailed(usize),
 
     #[error(
         "ERROR at {}:{} : Unexpected Number Of Argumentns, Expected  \"{}\", Found  \"{}\"",
@@ -38,7 +102,7 @@ pub enum Error {
         line: usize,
         col: usize,
         found: usize,
-        expected: usize,
+        expected: Arity,
     },
 }
 
@@ -49,3 +113,12 @@ fn print_vec_tokens(vec: &Vec<TokenKind>) -> String {
     }
     ouptut
 }
+
+fn print_vec_counts(vec: &[usize]) -> String {
+    let counts: Vec<String> = vec.iter().map(|count| count.to_string()).collect();
+    match counts.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
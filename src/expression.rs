@@ -1,166 +1,1086 @@
-use crate::error::Error;
+use std::rc::Rc;
+
+use crate::functions::Function;
+use crate::number::Number;
+use crate::symbols;
 
 #[derive(Clone, Copy, Debug)]
-pub enum Func {
-    Sqrt,
-    Sin,
-    Cos,
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mult,
+    Frac,
+    Pow,
+    Union,
+    Intersection,
+    SetMinus,
+    And,
+    Or,
+    Xor,
+    Implies,
+    Iff,
 }
 
-impl Func {
-    pub fn nr_of_args(&self) -> usize {
+impl BinaryOp {
+    pub fn precedence(&self) -> usize {
         match &self {
-            Func::Sqrt => 1,
-            Func::Sin => 1,
-            Func::Cos => 1,
+            BinaryOp::Iff => 0,
+            BinaryOp::Implies => 1,
+            BinaryOp::Or | BinaryOp::Xor => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Union | BinaryOp::SetMinus => 6,
+            BinaryOp::Mult | BinaryOp::Frac | BinaryOp::Intersection => 7,
+            BinaryOp::Pow => 9,
         }
     }
+
+    pub fn is_right_assoc(&self) -> bool {
+        matches!(self, BinaryOp::Pow | BinaryOp::Implies)
+    }
 }
 
-impl TryFrom<String> for Func {
-    type Error = Error;
+/// The relations that can be chained, as in `0 < x <= 1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelationOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Approx,
+    Sim,
+    Equiv,
+    Propto,
+    In,
+    NotIn,
+    Subset,
+    Supset,
+}
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
-            "sqrt" => Ok(Func::Sqrt),
-            "sin" => Ok(Func::Sin),
-            "cos" => Ok(Func::Cos),
-            a => Err(Error::UnexpectedIdent {
-                line: 0,
-                col: 0,
-                ident: a.to_string(),
-            }),
+impl RelationOp {
+    /// How the relation is written in `.mvi` source.
+    pub fn symbol(&self) -> &'static str {
+        match &self {
+            RelationOp::Eq => "=",
+            RelationOp::Ne => "!=",
+            RelationOp::Lt => "<",
+            RelationOp::Le => "<=",
+            RelationOp::Gt => ">",
+            RelationOp::Ge => ">=",
+            RelationOp::Approx => "~~",
+            RelationOp::Sim => "~",
+            RelationOp::Equiv => "-=",
+            RelationOp::Propto => "prop",
+            RelationOp::In => "in",
+            RelationOp::NotIn => "notin",
+            RelationOp::Subset => "subset",
+            RelationOp::Supset => "supset",
+        }
+    }
+
+    fn latex(&self) -> &'static str {
+        match &self {
+            RelationOp::Eq => "=",
+            RelationOp::Ne => "\\ne",
+            RelationOp::Lt => "<",
+            RelationOp::Le => "\\le",
+            RelationOp::Gt => ">",
+            RelationOp::Ge => "\\ge",
+            RelationOp::Approx => "\\approx",
+            RelationOp::Sim => "\\sim",
+            RelationOp::Equiv => "\\equiv",
+            RelationOp::Propto => "\\propto",
+            RelationOp::In => "\\in",
+            RelationOp::NotIn => "\\notin",
+            RelationOp::Subset => "\\subseteq",
+            RelationOp::Supset => "\\supseteq",
         }
     }
+
+    /// Whether the operand on the right is a set, as in `x in A`.
+    pub fn takes_set(&self) -> bool {
+        matches!(
+            self,
+            RelationOp::In | RelationOp::NotIn | RelationOp::Subset | RelationOp::Supset
+        )
+    }
+
+    /// Whether the operand on the left is a set, as in `A subset B`.
+    pub fn is_set_relation(&self) -> bool {
+        matches!(self, RelationOp::Subset | RelationOp::Supset)
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
-pub enum BinaryOp {
-    Add,
+pub enum UnaryOp {
     Sub,
-    Mult,
-    Frac,
-    Pow,
-    Equals,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn precedence(&self) -> usize {
+        match &self {
+            UnaryOp::Sub => UNARY_PRECEDENCE,
+            UnaryOp::Not => NOT_PRECEDENCE,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
-pub enum UnaryOp {
-    Sub,
+pub enum Quantifier {
+    ForAll,
+    Exists,
+    ExistsUnique,
+}
+
+impl Quantifier {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "forall" => Some(Quantifier::ForAll),
+            "exists" => Some(Quantifier::Exists),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self {
+            Quantifier::ForAll => "forall",
+            Quantifier::Exists => "exists",
+            Quantifier::ExistsUnique => "exists!",
+        }
+    }
+
+    fn latex(&self) -> &'static str {
+        match &self {
+            Quantifier::ForAll => "\\forall",
+            Quantifier::Exists => "\\exists",
+            Quantifier::ExistsUnique => "\\exists!",
+        }
+    }
+}
+
+/// The word introducing the remark attached to a statement.
+#[derive(Clone, Copy, Debug)]
+pub enum Annotation {
+    Where,
+    For,
+}
+
+impl Annotation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "where" => Some(Annotation::Where),
+            "for" => Some(Annotation::For),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self {
+            Annotation::Where => "where",
+            Annotation::For => "for",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DerivativeKind {
+    /// `dy/dx`, `d/dx(f)` or `diff(f, x, n)`
+    Leibniz,
+    /// `partial(f, x, y)`
+    Partial,
+    /// `f'(x)`, the number of primes and the arguments if any
+    Prime(usize, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum IntegralKind {
+    Int,
+    IInt,
+    IIInt,
+    OInt,
+}
+
+impl IntegralKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "int" => Some(IntegralKind::Int),
+            "iint" => Some(IntegralKind::IInt),
+            "iiint" => Some(IntegralKind::IIInt),
+            "oint" => Some(IntegralKind::OInt),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self {
+            IntegralKind::Int => "int",
+            IntegralKind::IInt => "iint",
+            IntegralKind::IIInt => "iiint",
+            IntegralKind::OInt => "oint",
+        }
+    }
+
+    pub fn nr_of_vars(&self) -> usize {
+        match &self {
+            IntegralKind::Int | IntegralKind::OInt => 1,
+            IntegralKind::IInt => 2,
+            IntegralKind::IIInt => 3,
+        }
+    }
+
+    fn latex(&self) -> &'static str {
+        match &self {
+            IntegralKind::Int => "\\int",
+            IntegralKind::IInt => "\\iint",
+            IntegralKind::IIInt => "\\iiint",
+            IntegralKind::OInt => "\\oint",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BigOpKind {
+    Sum,
+    Prod,
+    Union,
+    Intersection,
+}
+
+impl BigOpKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sum" => Some(BigOpKind::Sum),
+            "prod" => Some(BigOpKind::Prod),
+            "union" => Some(BigOpKind::Union),
+            "intersection" => Some(BigOpKind::Intersection),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self {
+            BigOpKind::Sum => "sum",
+            BigOpKind::Prod => "prod",
+            BigOpKind::Union => "union",
+            BigOpKind::Intersection => "intersection",
+        }
+    }
+
+    fn latex(&self) -> &'static str {
+        match &self {
+            BigOpKind::Sum => "\\sum",
+            BigOpKind::Prod => "\\prod",
+            BigOpKind::Union => "\\bigcup",
+            BigOpKind::Intersection => "\\bigcap",
+        }
+    }
+}
+
+/// The side a limit is approached from, `x -> a+` is from above.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Both,
+    Above,
+    Below,
+}
+
+#[derive(Clone, Debug)]
+pub enum Bounds {
+    /// An indefinite integral
+    None,
+    /// Lower and upper limits for each variable, innermost first
+    Each(Vec<(Expr, Expr)>),
+    /// A region or contour written under the integral sign
+    Region(Box<Expr>),
 }
 
 #[derive(Clone, Debug)]
 pub enum Expr {
     Unary(Box<Expr>, UnaryOp),
     Binary(Box<Expr>, Box<Expr>, BinaryOp),
-    Sym(char),
-    Val(f64),
-    Func(Func, Vec<Expr>),
+    Sym(String),
+    Val(Number),
+    Func(Rc<Function>, Vec<Expr>),
     Dots(),
+    /// An expression the user wrapped in parentheses.
+    Group(Box<Expr>),
+    /// `x_i`, a base with a subscript.
+    Sub(Box<Expr>, Box<Expr>),
+    /// A derivative of an expression with respect to each variable the given
+    /// number of times, prime derivatives have no variables.
+    Derivative(Box<Expr>, Vec<(Expr, usize)>, DerivativeKind),
+    /// An integrand integrated over each variable, innermost first.
+    Integral(Box<Expr>, Vec<Expr>, Bounds, IntegralKind),
+    /// A sum, product, union or intersection of the body over an index.
+    BigOp(Box<Expr>, Box<Expr>, Bounds, BigOpKind),
+    /// The limit of the body as the variable approaches the target.
+    Limit(Box<Expr>, Box<Expr>, Box<Expr>, Direction),
+    /// Rows of entries, all of the same length. A vector is a single column.
+    Matrix(Vec<Vec<Expr>>),
+    /// The first operand followed by each relation and its right hand side,
+    /// `a = b` is a chain of one.
+    Relation(Box<Expr>, Vec<(RelationOp, Expr)>),
+    /// Arms of a value and the condition it applies under, `None` for
+    /// `otherwise`.
+    Piecewise(Vec<(Expr, Option<Expr>)>),
+    /// A set of its elements, `{1, 2, 3}`.
+    Set(Vec<Expr>),
+    /// The elements satisfying a condition, `{x | x > 0}`.
+    SetBuilder(Box<Expr>, Box<Expr>),
+    /// Lower and upper bound, and whether each is included.
+    Interval(Box<Expr>, Box<Expr>, bool, bool),
+    /// A name such as `f` applied to arguments, `f(x, y)`.
+    Apply(String, Vec<Expr>),
+    /// `|x|`
+    Abs(Box<Expr>),
+    /// `||v||`
+    Norm(Box<Expr>),
+    /// `n!` for an order of 1 and the double factorial `n!!` for 2.
+    Factorial(Box<Expr>, usize),
+    /// `binom(n, k)`, or a multinomial coefficient with several lower entries.
+    Binomial(Box<Expr>, Vec<Expr>),
+    /// A quantified statement, the binder such as `x in R` and the body.
+    Quantified(Quantifier, Box<Expr>, Box<Expr>),
+    /// Words written in quotes, `"if"`.
+    Text(String),
+    /// A statement followed by `where` or `for` and a remark on it,
+    /// `v = d / t where t > 0`.
+    Annotated(Box<Expr>, Annotation, Box<Expr>),
 }
 
+/// Binding strength of a quantifier, whose body reaches as far as it can.
+pub const QUANTIFIER_PRECEDENCE: usize = 0;
+
+/// Binding strength of `not`, below relations so `not x = y` is `not (x = y)`.
+pub const NOT_PRECEDENCE: usize = 4;
+
+/// Binding strength of `=`, `<` and the other relations, the loosest after
+/// the logical connectives.
+pub const RELATION_PRECEDENCE: usize = 5;
+
+/// Binding strength of unary minus, it sits between `*` and `^` so that
+/// `-x^2` is `-(x^2)` while `-a * b` is `(-a) * b`.
+pub const UNARY_PRECEDENCE: usize = 8;
+
+/// Binding strength of anything that never needs parentheses.
+pub const ATOM_PRECEDENCE: usize = 10;
+
 impl Expr {
-    pub fn is_val(&self) -> bool {
+    /// The expression with any user written parentheses peeled off.
+    pub fn ungrouped(&self) -> &Expr {
+        match self {
+            Expr::Group(a) => a.ungrouped(),
+            a => a,
+        }
+    }
+
+    pub fn precedence(&self) -> usize {
         match &self {
-            Expr::Val(_) => true,
+            Expr::Binary(_, _, op) => op.precedence(),
+            Expr::Unary(_, op) => op.precedence(),
+            Expr::Relation(_, _) => RELATION_PRECEDENCE,
+            Expr::Quantified(_, _, _) | Expr::Annotated(_, _, _) => QUANTIFIER_PRECEDENCE,
+            Expr::Group(a) => a.precedence(),
+            // Their body runs on to the right like the factors of a product.
+            Expr::BigOp(_, _, _, _) | Expr::Integral(_, _, _, _) | Expr::Limit(_, _, _, _) => {
+                BinaryOp::Mult.precedence()
+            }
+            _ => ATOM_PRECEDENCE,
+        }
+    }
+
+    /// Whether this can stand for a matrix, being a matrix literal or an
+    /// uppercase letter like `A`, possibly with a subscript as in `A_1`.
+    fn is_matrix_like(&self) -> bool {
+        match self.ungrouped() {
+            Expr::Matrix(_) => true,
+            Expr::Sym(name) => name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase()),
+            Expr::Sub(base, _) => base.is_matrix_like(),
             _ => false,
         }
     }
+
+    /// Whether the rendered expression ends in the body of a sum, integral or
+    /// limit, which would take in anything multiplied after it.
+    fn ends_open(&self) -> bool {
+        match self {
+            Expr::BigOp(_, _, _, _) | Expr::Integral(_, _, _, _) | Expr::Limit(_, _, _, _) => true,
+            Expr::Binary(_, b, op) => {
+                !matches!(op, BinaryOp::Frac | BinaryOp::Pow) && b.ends_open()
+            }
+            Expr::Unary(a, _) | Expr::Group(a) => a.ends_open(),
+            _ => false,
+        }
+    }
+
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Unary(a, _)
+            | Expr::Group(a)
+            | Expr::Abs(a)
+            | Expr::Norm(a)
+            | Expr::Factorial(a, _) => vec![a],
+            Expr::Binomial(a, lower) => std::iter::once(&**a).chain(lower).collect(),
+            Expr::Binary(a, b, _)
+            | Expr::Sub(a, b)
+            | Expr::SetBuilder(a, b)
+            | Expr::Interval(a, b, _, _)
+            | Expr::Quantified(_, a, b)
+            | Expr::Annotated(a, _, b) => vec![a, b],
+            Expr::Set(elements) => elements.iter().collect(),
+            Expr::Func(_, args) | Expr::Apply(_, args) => args.iter().collect(),
+            Expr::Derivative(a, vars, kind) => {
+                let mut children = vec![&**a];
+                children.extend(vars.iter().map(|(var, _)| var));
+                if let DerivativeKind::Prime(_, args) = kind {
+                    children.extend(args);
+                }
+                children
+            }
+            Expr::Integral(a, vars, bounds, _) => {
+                let mut children = vec![&**a];
+                children.extend(vars);
+                match bounds {
+                    Bounds::None => {}
+                    Bounds::Each(limits) => {
+                        children.extend(limits.iter().flat_map(|(lower, upper)| [lower, upper]))
+                    }
+                    Bounds::Region(region) => children.push(region),
+                }
+                children
+            }
+            Expr::Limit(a, var, target, _) => vec![a, var, target],
+            Expr::Matrix(rows) => rows.iter().flatten().collect(),
+            Expr::Piecewise(arms) => arms
+                .iter()
+                .flat_map(|(value, condition)| std::iter::once(value).chain(condition))
+                .collect(),
+            Expr::Relation(a, rest) => {
+                let mut children = vec![&**a];
+                children.extend(rest.iter().map(|(_, b)| b));
+                children
+            }
+            Expr::BigOp(a, index, bounds, _) => {
+                let mut children = vec![&**a, &**index];
+                match bounds {
+                    Bounds::None => {}
+                    Bounds::Each(limits) => {
+                        children.extend(limits.iter().flat_map(|(lower, upper)| [lower, upper]))
+                    }
+                    Bounds::Region(region) => children.push(region),
+                }
+                children
+            }
+            Expr::Sym(_) | Expr::Val(_) | Expr::Text(_) | Expr::Dots() => vec![],
+        }
+    }
+}
+
+/// How identifiers longer than one letter are typeset.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum IdentStyle {
+    /// `\mathit{speed}`, italic like single letter variables
+    #[default]
+    Italic,
+    /// `\mathrm{speed}`, upright like words
+    Upright,
 }
 
-pub fn evaulate(expr: &Expr) -> String {
+/// How a power of a function like `sin(x)^2` is typeset.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum FuncPowerStyle {
+    /// `\sin^{2} x` for trigonometric and logarithmic functions
+    #[default]
+    Operator,
+    /// `{\sin(x)}^{2}`, raising the whole call
+    Argument,
+}
+
+/// How `a * b` is typeset.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum MultStyle {
+    /// `a b`, with a `\cdot` where leaving it out would misread
+    #[default]
+    Juxtapose,
+    /// `a \cdot b`
+    Cdot,
+    /// `a \times b`
+    Times,
+    /// `a \, b`, with a `\cdot` where leaving it out would misread
+    Thin,
+}
+
+/// The character between the whole and fractional part of a number.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum DecimalMark {
+    /// `3.14`
+    #[default]
+    Dot,
+    /// `3,14`
+    Comma,
+}
+
+impl DecimalMark {
+    pub fn latex(&self) -> &'static str {
+        match self {
+            DecimalMark::Dot => ".",
+            DecimalMark::Comma => "{,}",
+        }
+    }
+}
+
+/// How the digits of long numbers are grouped in threes.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum DigitGrouping {
+    /// `1000000`
+    #[default]
+    None,
+    /// `1\,000\,000`
+    Thin,
+    /// `1,000,000`
+    Comma,
+}
+
+/// The brackets around matrices, determinants always use vertical bars.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum MatrixStyle {
+    /// `bmatrix`, square brackets
+    #[default]
+    Bracket,
+    /// `pmatrix`, parentheses
+    Paren,
+}
+
+/// Options changing how expressions are turned into LaTeX.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Style {
+    pub identifiers: IdentStyle,
+    pub function_powers: FuncPowerStyle,
+    pub matrices: MatrixStyle,
+    pub multiplication: MultStyle,
+    pub decimal_mark: DecimalMark,
+    pub digit_grouping: DigitGrouping,
+    /// Render the parentheses written in the source even where precedence
+    /// doesn't need them.
+    pub keep_groups: bool,
+}
+
+pub fn evaulate(expr: &Expr, style: &Style) -> String {
     match expr {
         Expr::Binary(a, b, op) => {
-            let a_str = evaulate(a);
-            let b_str = evaulate(b);
+            let precedence = op.precedence();
+            let (a_min, b_min) = if op.is_right_assoc() {
+                (precedence + 1, precedence)
+            } else {
+                (precedence, precedence + 1)
+            };
+            let a_str = if a_min >= BinaryOp::Mult.precedence()
+                && a.ends_open()
+                && !is_kept_group(a, style)
+            {
+                format!("{{{}}}", parens(&evaulate(a, style), a))
+            } else {
+                evaulate_operand(a, a_min, style)
+            };
+            let b_str = evaulate_operand(b, b_min, style);
             match op {
-                BinaryOp::Add => {
-                    format!("{{{}}} + {{{}}}", a_str, b_str)
-                }
-                BinaryOp::Sub => {
-                    format!("{{{}}} - {{{}}}", a_str, b_str)
+                BinaryOp::Add => format!("{} + {}", a_str, b_str),
+                BinaryOp::Sub => format!("{} - {}", a_str, b_str),
+                // Words next to an expression, `"if" x > 0`, are only spaced out.
+                BinaryOp::Mult
+                    if matches!(a.ungrouped(), Expr::Text(_))
+                        || matches!(b.ungrouped(), Expr::Text(_)) =>
+                {
+                    format!("{} \\ {}", a_str, b_str)
                 }
                 BinaryOp::Mult => {
-                    let mut output = String::new();
-                    let a_weak = match **a {
-                        Expr::Binary(_, _, BinaryOp::Add) => true,
-                        Expr::Binary(_, _, BinaryOp::Sub) => true,
-                        _ => false,
+                    let visible = needs_operator(a, b);
+                    let operator = match style.multiplication {
+                        MultStyle::Juxtapose | MultStyle::Thin if visible => " \\cdot ",
+                        MultStyle::Juxtapose => "",
+                        MultStyle::Thin => " \\, ",
+                        MultStyle::Cdot => " \\cdot ",
+                        MultStyle::Times => " \\times ",
                     };
-                    if a_weak {
-                        output.push_str(&format!("{{({})}}", a_str));
-                    } else {
-                        output.push_str(&format!("{{{}}}", a_str));
-                    }
-
-                    if a.is_val() && b.is_val() {
-                        output.push_str("*");
-                    }
-                    let b_weak = match **b {
-                        Expr::Binary(_, _, BinaryOp::Add) => true,
-                        Expr::Binary(_, _, BinaryOp::Sub) => true,
-                        _ => false,
-                    };
-                    if b_weak {
-                        output.push_str(&format!("{{({})}}", b_str));
-                    } else {
-                        output.push_str(&format!("{{{}}}", b_str));
-                    }
-                    output
-                }
-                BinaryOp::Frac => {
-                    format!("\\frac{{{}}} {{{}}}", a_str, b_str)
+                    format!("{}{}{}", a_str, operator, b_str)
                 }
+                // The fraction bar and the raised exponent group on their own.
+                BinaryOp::Frac => format!(
+                    "\\frac{{{}}} {{{}}}",
+                    evaulate(a, style),
+                    evaulate(b, style)
+                ),
+                BinaryOp::Union => format!("{} \\cup {}", a_str, b_str),
+                BinaryOp::Intersection => format!("{} \\cap {}", a_str, b_str),
+                BinaryOp::SetMinus => format!("{} \\setminus {}", a_str, b_str),
+                BinaryOp::And => format!("{} \\land {}", a_str, b_str),
+                BinaryOp::Or => format!("{} \\lor {}", a_str, b_str),
+                BinaryOp::Xor => format!("{} \\oplus {}", a_str, b_str),
+                BinaryOp::Implies => format!("{} \\Rightarrow {}", a_str, b_str),
+                BinaryOp::Iff => format!("{} \\Leftrightarrow {}", a_str, b_str),
                 BinaryOp::Pow => {
-                    format!("{{{}}} ^ {{{}}}", a_str, b_str)
-                }
-                BinaryOp::Equals => {
-                    format!("{{{}}} = {{{}}}", a_str, b_str)
+                    // `A^T` is the transpose, drawn like `transpose(A)`, while
+                    // `e^T` stays a power of the variable `T`.
+                    let b_str = match b.ungrouped() {
+                        Expr::Sym(name) if name == "T" && a.is_matrix_like() => {
+                            "\\mathsf{T}".to_string()
+                        }
+                        _ => evaulate(b, style),
+                    };
+                    match a.ungrouped() {
+                        // `\sin^{-1} x` would read as the arcsine, so only
+                        // positive whole powers go on the operator.
+                        Expr::Func(func, args)
+                            if matches!(style.function_powers, FuncPowerStyle::Operator)
+                                && func.operator.is_some()
+                                && matches!(b.ungrouped(), Expr::Val(n)
+                                    if n.value.fract() == 0.0 && n.value >= 1.0) =>
+                        {
+                            let operator = func.operator.as_deref().unwrap_or_default();
+                            let arg = evaulate(&args[0], style);
+                            if matches!(
+                                args[0].ungrouped(),
+                                Expr::Sym(_) | Expr::Val(_) | Expr::Sub(_, _)
+                            ) {
+                                format!("{}^{{{}}} {}", operator, b_str, arg)
+                            } else {
+                                format!("{}^{{{}}}{}", operator, b_str, parens(&arg, &args[0]))
+                            }
+                        }
+                        // Keep `x_i^2` stacked rather than raising the whole `{x_i}`.
+                        Expr::Sub(_, _) if !is_kept_group(a, style) => {
+                            format!("{} ^ {{{}}}", evaulate(a, style), b_str)
+                        }
+                        _ => format!("{} ^ {{{}}}", evaulate_base(a, style), b_str),
+                    }
                 }
             }
         }
         Expr::Unary(a, op) => {
-            let a = evaulate(a);
+            let a = evaulate_operand(a, op.precedence(), style);
             match op {
-                UnaryOp::Sub => {
-                    format!("-{{{}}}", a)
-                }
+                UnaryOp::Sub => format!("-{}", a),
+                UnaryOp::Not => format!("\\neg {}", a),
             }
         }
-        Expr::Sym(c) => format!("{{{}}}", c.to_string()),
-        Expr::Val(v) => format!("{{{}}}", v.to_string()),
-        Expr::Func(func, args) => match func {
-            Func::Sqrt => {
-                assert_eq!(
-                    args.len(),
-                    1,
-                    "ERROR: Square Root Accepts One Argument, You Supplied {}",
-                    args.len()
-                );
-
-                format!("\\sqrt{{{}}}", evaulate(&args[0]))
+        Expr::Sym(name) => match symbols::lookup(name) {
+            Some(symbol) => format!("{{{}}}", symbol.latex),
+            None if name.chars().count() == 1 => format!("{{{}}}", name),
+            None => match style.identifiers {
+                IdentStyle::Italic => format!("{{\\mathit{{{}}}}}", name),
+                IdentStyle::Upright => format!("{{\\mathrm{{{}}}}}", name),
+            },
+        },
+        Expr::Val(v) => format!("{{{}}}", v.latex(style.decimal_mark, style.digit_grouping)),
+        // The determinant of a matrix written out is typeset with bars.
+        Expr::Func(func, args)
+            if func.name == "det" && matches!(args[0].ungrouped(), Expr::Matrix(_)) =>
+        {
+            let Expr::Matrix(rows) = args[0].ungrouped() else {
+                unreachable!()
+            };
+            evaulate_matrix(rows, "vmatrix", style)
+        }
+        Expr::Func(func, args) => {
+            let tall = args.iter().any(is_tall);
+            let args: Vec<String> = args.iter().map(|a| evaulate(a, style)).collect();
+            func.render(&args, tall)
+        }
+        Expr::Dots() => "{{{\\dots}}}".to_string(),
+        Expr::Group(a) if style.keep_groups => parens(&evaulate(a, style), a),
+        Expr::Group(a) => evaulate(a, style),
+        Expr::Derivative(a, vars, kind) => evaulate_derivative(a, vars, kind, style),
+        Expr::Integral(a, vars, bounds, kind) => evaulate_integral(a, vars, bounds, kind, style),
+        Expr::Relation(a, rest) => {
+            let mut output = evaulate_operand(a, RELATION_PRECEDENCE + 1, style);
+            for (op, b) in rest {
+                let b = if op.takes_set() {
+                    evaulate_set(b, style)
+                } else {
+                    evaulate_operand(b, RELATION_PRECEDENCE + 1, style)
+                };
+                output.push_str(&format!(" {} {}", op.latex(), b));
             }
-            Func::Sin => {
-                assert_eq!(
-                    args.len(),
-                    1,
-                    "ERROR: Square Root Accepts One Argument, You Supplied {}",
-                    args.len()
-                );
-
-                format!("\\sin({{{}}})", evaulate(&args[0]))
+            output
+        }
+        Expr::Quantified(quantifier, binder, body) => format!(
+            "{} {} \\colon {}",
+            quantifier.latex(),
+            evaulate(binder, style),
+            evaulate(body, style)
+        ),
+        Expr::Text(text) => format!("\\text{{{}}}", escape_text(text)),
+        Expr::Annotated(a, annotation, b) => format!(
+            "{}, \\quad \\text{{{} }} {}",
+            evaulate(a, style),
+            annotation.name(),
+            evaulate(b, style)
+        ),
+        Expr::Set(elements) if elements.is_empty() => "\\{\\}".to_string(),
+        Expr::Set(elements) => {
+            let elements: Vec<String> = elements.iter().map(|e| evaulate(e, style)).collect();
+            format!("\\{{{}\\}}", elements.join(", "))
+        }
+        Expr::SetBuilder(a, b) => format!(
+            "\\{{{} \\mid {}\\}}",
+            evaulate(a, style),
+            evaulate(b, style)
+        ),
+        Expr::Interval(a, b, closed_a, closed_b) => delimit(
+            if *closed_a { "[" } else { "(" },
+            if *closed_b { "]" } else { ")" },
+            &format!("{}, {}", evaulate(a, style), evaulate(b, style)),
+            is_tall(a) || is_tall(b),
+        ),
+        Expr::Piecewise(arms) => {
+            let arms: Vec<String> = arms
+                .iter()
+                .map(|(value, condition)| {
+                    let condition = match condition {
+                        Some(condition) => evaulate(condition, style),
+                        None => "\\text{otherwise}".to_string(),
+                    };
+                    format!("{} & {}", evaulate(value, style), condition)
+                })
+                .collect();
+            format!("\\begin{{cases}} {} \\end{{cases}}", arms.join(" \\\\ "))
+        }
+        Expr::Matrix(rows) => {
+            let environment = match style.matrices {
+                MatrixStyle::Bracket => "bmatrix",
+                MatrixStyle::Paren => "pmatrix",
+            };
+            evaulate_matrix(rows, environment, style)
+        }
+        Expr::Limit(a, var, target, direction) => {
+            let direction = match direction {
+                Direction::Both => "",
+                Direction::Above => "^{+}",
+                Direction::Below => "^{-}",
+            };
+            let mut output = format!(
+                "\\lim_{{{} \\to {{{}}}{}}}",
+                evaulate(var, style),
+                evaulate(target, style),
+                direction
+            );
+            output.push(' ');
+            output.push_str(&evaulate_operand(a, BinaryOp::Mult.precedence(), style));
+            output
+        }
+        Expr::BigOp(a, index, bounds, kind) => {
+            let index = evaulate(index, style);
+            let mut output = match bounds {
+                Bounds::None => format!("{}_{{{}}}", kind.latex(), index),
+                Bounds::Region(set) => {
+                    format!(
+                        "{}_{{{} \\in {}}}",
+                        kind.latex(),
+                        index,
+                        evaulate(set, style)
+                    )
+                }
+                Bounds::Each(limits) => {
+                    let (lower, upper) = &limits[0];
+                    format!(
+                        "{}_{{{} = {}}}^{{{}}}",
+                        kind.latex(),
+                        index,
+                        evaulate(lower, style),
+                        evaulate(upper, style)
+                    )
+                }
+            };
+            output.push(' ');
+            output.push_str(&evaulate_operand(a, BinaryOp::Mult.precedence(), style));
+            output
+        }
+        Expr::Apply(name, args) => {
+            let tall = args.iter().any(is_tall);
+            let args: Vec<String> = args.iter().map(|a| evaulate(a, style)).collect();
+            format!(
+                "{}{}",
+                evaulate(&Expr::Sym(name.clone()), style),
+                delimit("(", ")", &args.join(", "), tall)
+            )
+        }
+        Expr::Abs(a) => format!("\\left| {} \\right|", evaulate(a, style)),
+        Expr::Norm(a) => format!("\\left\\lVert {} \\right\\rVert", evaulate(a, style)),
+        Expr::Factorial(a, order) => {
+            let bangs = "!".repeat(*order);
+            if matches!(a.ungrouped(), Expr::Factorial(_, _)) && !is_kept_group(a, style) {
+                format!("{{{}}}{}", parens(&evaulate(a, style), a), bangs)
+            } else {
+                format!("{}{}", evaulate_base(a, style), bangs)
+            }
+        }
+        Expr::Binomial(a, lower) => {
+            let lower: Vec<String> = lower.iter().map(|k| evaulate(k, style)).collect();
+            format!("\\binom{{{}}}{{{}}}", evaulate(a, style), lower.join(", "))
+        }
+        Expr::Sub(a, b) => format!("{}_{{{}}}", evaulate_base(a, style), evaulate(b, style)),
+    }
+}
+
+fn evaulate_derivative(
+    expr: &Expr,
+    vars: &[(Expr, usize)],
+    kind: &DerivativeKind,
+    style: &Style,
+) -> String {
+    let power = |order: usize| {
+        if order == 1 {
+            String::new()
+        } else {
+            format!("^{{{}}}", order)
+        }
+    };
+
+    let d = match kind {
+        DerivativeKind::Leibniz => "d",
+        DerivativeKind::Partial => "\\partial",
+        DerivativeKind::Prime(order, args) => {
+            let primes = match order {
+                1..=3 => "'".repeat(*order),
+                _ => format!("^{{({})}}", order),
+            };
+            let base = evaulate_base(expr, style);
+            if args.is_empty() {
+                return format!("{}{}", base, primes);
             }
-            Func::Cos => {
-                assert_eq!(
-                    args.len(),
-                    1,
-                    "ERROR: Square Root Accepts One Argument, You Supplied {}",
-                    args.len()
-                );
-
-                format!("\\cos({{{}}})", evaulate(&args[0]))
+            let tall = args.iter().any(is_tall);
+            let args: Vec<String> = args.iter().map(|a| evaulate(a, style)).collect();
+            return format!(
+                "{}{}{}",
+                base,
+                primes,
+                delimit("(", ")", &args.join(", "), tall)
+            );
+        }
+    };
+
+    let order: usize = vars.iter().map(|(_, order)| order).sum();
+    let denominator: Vec<String> = vars
+        .iter()
+        .map(|(var, order)| format!("{} {}{}", d, evaulate(var, style), power(*order)))
+        .collect();
+    let denominator = denominator.join(" ");
+
+    if matches!(expr.ungrouped(), Expr::Sym(_) | Expr::Sub(_, _)) {
+        format!(
+            "\\frac{{{}{} {}}}{{{}}}",
+            d,
+            power(order),
+            evaulate(expr, style),
+            denominator
+        )
+    } else {
+        format!(
+            "\\frac{{{}{}}}{{{}}}{}",
+            d,
+            power(order),
+            denominator,
+            parens(&evaulate(expr, style), expr)
+        )
+    }
+}
+
+/// Escapes the characters LaTeX treats specially inside `\text{}`.
+fn escape_text(text: &str) -> String {
+    let mut output = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => output.push_str("\\textbackslash{}"),
+            '^' => output.push_str("\\textasciicircum{}"),
+            '~' => output.push_str("\\textasciitilde{}"),
+            '{' | '}' | '$' | '&' | '%' | '#' | '_' => {
+                output.push('\\');
+                output.push(c);
             }
-        },
-        Expr::Dots() => "{{{\\dots}}}".to_string(),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Wraps rendered LaTeX in parentheses, sized with `\left( \right)` when
+/// `expr` holds something taller than a line such as a fraction.
+fn parens(latex: &str, expr: &Expr) -> String {
+    delimit("(", ")", latex, is_tall(expr))
+}
+
+/// Puts `open` and `close` around rendered LaTeX, letting them grow with
+/// `\left` and `\right` around tall content.
+fn delimit(open: &str, close: &str, latex: &str, tall: bool) -> String {
+    if tall {
+        format!("\\left{} {} \\right{}", open, latex, close)
+    } else {
+        format!("{}{}{}", open, latex, close)
+    }
+}
+
+fn is_tall(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Binary(_, _, BinaryOp::Frac)
+            | Expr::Derivative(_, _, DerivativeKind::Leibniz | DerivativeKind::Partial)
+            | Expr::Integral(_, _, _, _)
+            | Expr::BigOp(_, _, _, _)
+            | Expr::Matrix(_)
+            | Expr::Piecewise(_)
+            | Expr::Binomial(_, _)
+    ) || expr.children().into_iter().any(is_tall)
+}
+
+/// Whether `expr` is parentheses the user wrote that are being kept, so they
+/// are rendered by the group itself.
+fn is_kept_group(expr: &Expr, style: &Style) -> bool {
+    style.keep_groups && matches!(expr, Expr::Group(_))
+}
+
+/// Renders an operand, in parentheses when it binds looser than
+/// `min_precedence`. A fraction never needs them next to other operators,
+/// while `6.02 \times 10^{23}` is a product that a minus sign may lead.
+fn evaulate_operand(expr: &Expr, min_precedence: usize, style: &Style) -> String {
+    let output = evaulate(expr, style);
+    let precedence = match expr.ungrouped() {
+        Expr::Binary(_, _, BinaryOp::Frac) => ATOM_PRECEDENCE,
+        Expr::Val(v) if v.is_scientific() => UNARY_PRECEDENCE,
+        // Anything following these is caught by `ends_open` instead.
+        Expr::BigOp(_, _, _, _) | Expr::Integral(_, _, _, _) | Expr::Limit(_, _, _, _) => {
+            ATOM_PRECEDENCE
+        }
+        expr => expr.precedence(),
+    };
+    if precedence < min_precedence && !is_kept_group(expr, style) {
+        format!("{{{}}}", parens(&output, expr))
+    } else {
+        format!("{{{}}}", output)
+    }
+}
+
+/// Renders the base of a power, subscript, factorial or prime, which is in
+/// parentheses unless it is a single atom, fractions included.
+fn evaulate_base(expr: &Expr, style: &Style) -> String {
+    let output = evaulate(expr, style);
+    let scientific = matches!(expr.ungrouped(), Expr::Val(v) if v.is_scientific());
+    if (expr.precedence() < ATOM_PRECEDENCE || scientific) && !is_kept_group(expr, style) {
+        format!("{{{}}}", parens(&output, expr))
+    } else {
+        format!("{{{}}}", output)
+    }
+}
+
+/// Whether `a b` written without an operator would misread, as `2 3` reads as
+/// 23, `a -b` as a subtraction, `2 \frac{1}{2}` as a mixed number and two
+/// fractions next to each other as one.
+fn needs_operator(a: &Expr, b: &Expr) -> bool {
+    fn starts_with_number(expr: &Expr) -> bool {
+        match expr.ungrouped() {
+            Expr::Val(_) => true,
+            Expr::Binary(a, _, BinaryOp::Mult | BinaryOp::Pow)
+            | Expr::Factorial(a, _)
+            | Expr::Sub(a, _) => starts_with_number(a),
+            _ => false,
+        }
+    }
+    fn starts_with_fraction(expr: &Expr) -> bool {
+        match expr.ungrouped() {
+            Expr::Binary(_, _, BinaryOp::Frac) => true,
+            Expr::Binary(a, _, BinaryOp::Mult) => starts_with_fraction(a),
+            _ => false,
+        }
+    }
+    fn ends_with_fraction(expr: &Expr) -> bool {
+        match expr.ungrouped() {
+            Expr::Binary(_, _, BinaryOp::Frac) => true,
+            Expr::Binary(_, b, BinaryOp::Mult) => ends_with_fraction(b),
+            _ => false,
+        }
+    }
+    fn ends_with_number(expr: &Expr) -> bool {
+        match expr.ungrouped() {
+            Expr::Val(_) => true,
+            Expr::Binary(_, b, BinaryOp::Mult) => ends_with_number(b),
+            _ => false,
+        }
+    }
+
+    starts_with_number(b)
+        || matches!(b.ungrouped(), Expr::Unary(_, UnaryOp::Sub))
+        || ((ends_with_fraction(a) || ends_with_number(a)) && starts_with_fraction(b))
+}
+
+/// Renders the right hand side of `in` or `subset`, where the single letters
+/// `N`, `Z`, `Q`, `R` and `C` are the number sets.
+fn evaulate_set(expr: &Expr, style: &Style) -> String {
+    match expr.ungrouped() {
+        Expr::Sym(name) if matches!(name.as_str(), "N" | "Z" | "Q" | "R" | "C") => {
+            format!("{{\\mathbb{{{}}}}}", name)
+        }
+        _ => format!("{{{}}}", evaulate(expr, style)),
+    }
+}
+
+fn evaulate_matrix(rows: &[Vec<Expr>], environment: &str, style: &Style) -> String {
+    let rows: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|entry| evaulate(entry, style))
+                .collect::<Vec<String>>()
+                .join(" & ")
+        })
+        .collect();
+    format!(
+        "\\begin{{{0}}} {1} \\end{{{0}}}",
+        environment,
+        rows.join(" \\\\ ")
+    )
+}
+
+fn evaulate_integral(
+    expr: &Expr,
+    vars: &[Expr],
+    bounds: &Bounds,
+    kind: &IntegralKind,
+    style: &Style,
+) -> String {
+    let mut output = String::new();
+    match bounds {
+        Bounds::None => output.push_str(kind.latex()),
+        Bounds::Region(region) => {
+            output.push_str(&format!("{}_{{{}}}", kind.latex(), evaulate(region, style)))
+        }
+        // One integral sign per variable, the outermost written first. A
+        // contour integral has a single variable and keeps its circle.
+        Bounds::Each(limits) => {
+            let sign = match kind {
+                IntegralKind::OInt => kind.latex(),
+                _ => IntegralKind::Int.latex(),
+            };
+            let signs: Vec<String> = limits
+                .iter()
+                .rev()
+                .map(|(lower, upper)| {
+                    format!(
+                        "{}_{{{}}}^{{{}}}",
+                        sign,
+                        evaulate(lower, style),
+                        evaulate(upper, style)
+                    )
+                })
+                .collect();
+            output.push_str(&signs.join(" "));
+        }
+    }
+
+    output.push(' ');
+    output.push_str(&evaulate_operand(expr, BinaryOp::Mult.precedence(), style));
+    for var in vars {
+        output.push_str(&format!(" \\, d{}", evaulate(var, style)));
     }
+    output
 }
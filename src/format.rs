@@ -0,0 +1,448 @@
+use crate::error::Error;
+use crate::expression::{
+    BinaryOp, Bounds, DerivativeKind, Direction, Expr, UnaryOp, ATOM_PRECEDENCE,
+    RELATION_PRECEDENCE,
+};
+use crate::functions::FunctionRegistry;
+use crate::lexer::{Comment, TokenKind};
+use crate::parser::{is_differential_name, parse_document, Statement};
+
+/// Parses `source` and prints it back in the canonical `.mvi` style.
+pub fn format_source(source: &str, functions: &FunctionRegistry) -> Result<String, Error> {
+    let (statements, rest) = parse_document(source, functions)?;
+    Ok(format_statements(&statements, &rest))
+}
+
+/// Prints one statement per line with its comments, keeping a single blank
+/// line wherever the original source had one or more empty lines.
+pub fn format_statements(statements: &[Statement], rest: &[Comment]) -> String {
+    let mut output = String::new();
+    let mut previous_end: Option<usize> = None;
+
+    let mut separate = |output: &mut String, line: usize, end_line: usize| {
+        if previous_end.is_some_and(|previous_end| line > previous_end + 1) {
+            output.push('\n');
+        }
+        previous_end = Some(end_line);
+    };
+
+    for statement in statements {
+        for comment in &statement.comments {
+            // A comment moved up out of the statement is measured from the
+            // statement's first line, right above which it ends up.
+            separate(
+                &mut output,
+                comment.line.min(statement.line),
+                comment.end_line.min(statement.line),
+            );
+            output.push_str(&format!("{}\n", comment));
+        }
+
+        let end_line = statement
+            .trailing
+            .iter()
+            .map(|comment| comment.end_line)
+            .fold(statement.end_line, usize::max);
+        separate(&mut output, statement.line, end_line);
+        output.push_str(&format_expr(&statement.expr));
+        output.push(';');
+        for comment in &statement.trailing {
+            output.push_str(&format!(" {}", comment));
+        }
+        output.push('\n');
+    }
+
+    for comment in rest {
+        separate(&mut output, comment.line, comment.end_line);
+        output.push_str(&format!("{}\n", comment));
+    }
+
+    output
+}
+
+pub fn format_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(a, b, op) => {
+            let precedence = op.precedence();
+            let (a_min, b_min) = if op.is_right_assoc() {
+                (precedence + 1, precedence)
+            } else {
+                (precedence, precedence + 1)
+            };
+            let a_str = format_operand(a, a_min);
+            let b_str = format_operand(b, b_min);
+            match op {
+                BinaryOp::Add => format!("{} + {}", a_str, b_str),
+                BinaryOp::Sub => format!("{} - {}", a_str, b_str),
+                BinaryOp::Mult
+                    if matches!(a.ungrouped(), Expr::Text(_))
+                        || matches!(b.ungrouped(), Expr::Text(_)) =>
+                {
+                    format!("{} {}", a_str, b_str)
+                }
+                BinaryOp::Mult => format!("{} * {}", a_str, b_str),
+                BinaryOp::Frac => format!("{} / {}", a_str, b_str),
+                BinaryOp::Pow => format!("{}^{}", a_str, b_str),
+                BinaryOp::Union => format!("{} union {}", a_str, b_str),
+                BinaryOp::Intersection => format!("{} intersection {}", a_str, b_str),
+                BinaryOp::SetMinus => format!("{} setminus {}", a_str, b_str),
+                BinaryOp::And => format!("{} and {}", a_str, b_str),
+                BinaryOp::Or => format!("{} or {}", a_str, b_str),
+                BinaryOp::Xor => format!("{} xor {}", a_str, b_str),
+                BinaryOp::Implies => format!("{} => {}", a_str, b_str),
+                BinaryOp::Iff => format!("{} <=> {}", a_str, b_str),
+            }
+        }
+        Expr::Relation(a, rest) => {
+            let mut output = format_operand(a, RELATION_PRECEDENCE + 1);
+            for (op, b) in rest {
+                output.push_str(&format!(
+                    " {} {}",
+                    op.symbol(),
+                    format_operand(b, RELATION_PRECEDENCE + 1)
+                ));
+            }
+            output
+        }
+        Expr::Unary(a, op) => {
+            let a_str = format_operand(a, op.precedence());
+            match op {
+                UnaryOp::Sub => format!("-{}", a_str),
+                UnaryOp::Not => format!("not {}", a_str),
+            }
+        }
+        Expr::Quantified(quantifier, binder, body) => format!(
+            "{} {}: {}",
+            quantifier.name(),
+            format_operand(binder, RELATION_PRECEDENCE),
+            format_expr(body)
+        ),
+        Expr::Annotated(a, annotation, b) => {
+            format!(
+                "{} {} {}",
+                format_expr(a),
+                annotation.name(),
+                format_expr(b)
+            )
+        }
+        Expr::Text(text) => TokenKind::Text(text.clone()).to_string(),
+        Expr::Sym(c) => c.to_string(),
+        Expr::Val(v) => v.to_string(),
+        Expr::Func(func, args) => {
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!("{}({})", func.name, args.join(", "))
+        }
+        Expr::Apply(name, args) => {
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Expr::Dots() => "...".to_string(),
+        Expr::Group(a) => format_expr(a),
+        Expr::Derivative(a, vars, kind) => format_derivative(a, vars, kind),
+        Expr::Integral(a, vars, bounds, kind) => {
+            let mut args = vec![format_expr(a)];
+            match bounds {
+                Bounds::None => args.extend(vars.iter().map(format_expr)),
+                Bounds::Region(region) => {
+                    args.extend(vars.iter().map(format_expr));
+                    args.push(format_expr(region));
+                }
+                Bounds::Each(limits) => {
+                    for (var, (lower, upper)) in vars.iter().zip(limits) {
+                        args.extend([format_expr(var), format_expr(lower), format_expr(upper)]);
+                    }
+                }
+            }
+            format!("{}({})", kind.name(), args.join(", "))
+        }
+        Expr::Matrix(rows) if rows.iter().all(|row| row.len() == 1) => {
+            let entries: Vec<String> = rows.iter().flatten().map(format_expr).collect();
+            format!("[{}]", entries.join(", "))
+        }
+        Expr::Matrix(rows) => {
+            let rows: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let entries: Vec<String> = row.iter().map(format_expr).collect();
+                    format!("[{}]", entries.join(", "))
+                })
+                .collect();
+            format!("[{}]", rows.join(", "))
+        }
+        Expr::Set(elements) => {
+            let elements: Vec<String> = elements.iter().map(format_expr).collect();
+            format!("{{{}}}", elements.join(", "))
+        }
+        Expr::SetBuilder(a, b) => format!("{{{} | {}}}", format_expr(a), format_expr(b)),
+        Expr::Interval(a, b, closed_a, closed_b) => format!(
+            "{}{}, {}{}",
+            if *closed_a { "[" } else { "(" },
+            format_expr(a),
+            format_expr(b),
+            if *closed_b { "]" } else { ")" }
+        ),
+        Expr::Piecewise(arms) => {
+            let arms: Vec<String> = arms
+                .iter()
+                .map(|(value, condition)| {
+                    let condition = match condition {
+                        Some(condition) => format_expr(condition),
+                        None => "otherwise".to_string(),
+                    };
+                    format!("{}, {}", format_expr(value), condition)
+                })
+                .collect();
+            format!("cases({})", arms.join("; "))
+        }
+        Expr::Limit(a, var, target, direction) => {
+            let direction = match direction {
+                Direction::Both => "",
+                Direction::Above => "+",
+                Direction::Below => "-",
+            };
+            format!(
+                "lim({}, {} -> {}{})",
+                format_expr(a),
+                format_expr(var),
+                format_expr(target),
+                direction
+            )
+        }
+        Expr::BigOp(a, index, bounds, kind) => {
+            let mut args = vec![format_expr(a), format_expr(index)];
+            match bounds {
+                Bounds::None => {}
+                Bounds::Region(set) => args.push(format_expr(set)),
+                Bounds::Each(limits) => {
+                    for (lower, upper) in limits {
+                        args.extend([format_expr(lower), format_expr(upper)]);
+                    }
+                }
+            }
+            format!("{}({})", kind.name(), args.join(", "))
+        }
+        // `||x||` would read back as a norm, so bars inside bars are spelled out.
+        Expr::Abs(a) => match format_expr(a) {
+            inner if inner.starts_with('|') => format!("abs({})", inner),
+            inner => format!("|{}|", inner),
+        },
+        Expr::Norm(a) => match format_expr(a) {
+            inner if inner.starts_with('|') => format!("norm({})", inner),
+            inner => format!("||{}||", inner),
+        },
+        Expr::Factorial(a, order) => {
+            let bangs = "!".repeat(*order);
+            match a.ungrouped() {
+                Expr::Factorial(_, _) => format!("({}){}", format_expr(a), bangs),
+                _ => format!("{}{}", format_operand(a, ATOM_PRECEDENCE), bangs),
+            }
+        }
+        Expr::Binomial(a, lower) => {
+            let args: Vec<String> = std::iter::once(&**a)
+                .chain(lower)
+                .map(format_expr)
+                .collect();
+            format!("binom({})", args.join(", "))
+        }
+        Expr::Sub(a, b) => {
+            let a_str = format_operand(a, ATOM_PRECEDENCE);
+            match b.ungrouped() {
+                Expr::Sym(_) | Expr::Val(_) => {
+                    format!("{}_{}", a_str, format_expr(b))
+                }
+                _ => format!("{}_{{{}}}", a_str, format_expr(b)),
+            }
+        }
+    }
+}
+
+fn format_derivative(expr: &Expr, vars: &[(Expr, usize)], kind: &DerivativeKind) -> String {
+    let power = |order: usize| match order {
+        1 => String::new(),
+        n => format!("^{}", n),
+    };
+
+    match kind {
+        DerivativeKind::Prime(order, args) => {
+            let primes = "'".repeat(*order);
+            let expr = format_operand(expr, ATOM_PRECEDENCE);
+            if args.is_empty() {
+                return format!("{}{}", expr, primes);
+            }
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!("{}{}({})", expr, primes, args.join(", "))
+        }
+        DerivativeKind::Partial => {
+            let mut args = vec![format_expr(expr)];
+            for (var, order) in vars {
+                args.extend(std::iter::repeat_n(format_expr(var), *order));
+            }
+            format!("partial({})", args.join(", "))
+        }
+        DerivativeKind::Leibniz => match vars {
+            [(Expr::Sym(var), order)] if is_differential_name(var) => match expr {
+                Expr::Sym(function) if is_differential_name(function) => {
+                    format!("d{}{}/d{}{}", power(*order), function, var, power(*order))
+                }
+                _ => format!(
+                    "d{}/d{}{}({})",
+                    power(*order),
+                    var,
+                    power(*order),
+                    format_expr(expr)
+                ),
+            },
+            [(var, 1)] => format!("diff({}, {})", format_expr(expr), format_expr(var)),
+            [(var, order)] => format!(
+                "diff({}, {}, {})",
+                format_expr(expr),
+                format_expr(var),
+                order
+            ),
+            _ => unreachable!("Leibniz derivatives have one variable"),
+        },
+    }
+}
+
+/// Formats `expr`, wrapping it in parentheses when it binds looser than
+/// `min_precedence` requires. Sums, integrals and limits are written as
+/// calls, so unlike in LaTeX their body never runs on.
+fn format_operand(expr: &Expr, min_precedence: usize) -> String {
+    let output = format_expr(expr);
+    let precedence = match expr.ungrouped() {
+        Expr::BigOp(_, _, _, _) | Expr::Integral(_, _, _, _) | Expr::Limit(_, _, _, _) => {
+            ATOM_PRECEDENCE
+        }
+        expr => expr.precedence(),
+    };
+    if precedence < min_precedence {
+        format!("({})", output)
+    } else {
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::{evaulate, Style};
+
+    /// The LaTeX of each statement in `source` with the default style.
+    fn latex(source: &str, functions: &FunctionRegistry) -> Vec<String> {
+        let (statements, _) = parse_document(source, functions)
+            .unwrap_or_else(|error| panic!("{} in\n{}", error, source));
+        statements
+            .iter()
+            .map(|statement| evaulate(&statement.expr, &Style::default()))
+            .collect()
+    }
+
+    /// Formats `source` twice, checking the second pass changes nothing and
+    /// that the formatted source renders the same as the original.
+    fn assert_idempotent(source: &str) {
+        let functions = FunctionRegistry::default();
+        let once = format_source(source, &functions)
+            .unwrap_or_else(|error| panic!("{} in\n{}", error, source));
+        let twice = format_source(&once, &functions)
+            .unwrap_or_else(|error| panic!("{} in\n{}", error, once));
+        assert_eq!(once, twice, "formatting is not stable for\n{}", source);
+        assert_eq!(
+            latex(source, &functions),
+            latex(&once, &functions),
+            "formatting changes the rendering of\n{}",
+            source
+        );
+    }
+
+    /// The code blocks of the README that hold `.mvi` source, those without
+    /// a language.
+    fn readme_blocks() -> Vec<String> {
+        let mut blocks = vec![];
+        // The language of the open block along with its text so far.
+        let mut block: Option<(&str, String)> = None;
+        for line in include_str!("../README.md").lines() {
+            match (line.strip_prefix("```"), &mut block) {
+                (Some(_), Some(("", text))) => {
+                    blocks.push(std::mem::take(text));
+                    block = None;
+                }
+                (Some(_), Some(_)) => block = None,
+                (Some(language), None) => block = Some((language, String::new())),
+                (None, Some((_, text))) => {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+                (None, None) => {}
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn readme_examples_are_stable() {
+        let blocks = readme_blocks();
+        assert!(!blocks.is_empty());
+        for block in blocks {
+            assert_idempotent(&block);
+        }
+    }
+
+    #[test]
+    fn example_files_are_stable() {
+        for source in [
+            include_str!("../example/basic.mvi"),
+            include_str!("../example/example.mvi"),
+            include_str!("../example/power.mvi"),
+            include_str!("../example/random.mvi"),
+        ] {
+            assert_idempotent(source);
+        }
+    }
+
+    #[test]
+    fn notation_is_stable() {
+        for source in [
+            "y = a - (b - c) + (a + b) * c / (d / e);",
+            "y = -x^2 + (-x)^2 + 2^3^4 + (2^3)^4;",
+            "y = sin(x)^2 + sin^2(x) + log(x, 2) + T_max;",
+            "y = 2x + 2(a + b) + f(x) + x(a + b);",
+            "y = 1.50 + 6.02e23 + 1_000 + 0xFF + 0b1010;",
+            "y = d/dx(x^2) + dy/dx + diff(f, x, 2) + partial(f, x, y);",
+            "y = int(f, x, 0, 1) + oint(f, s, C) + sum(a_k, k, 1, n) * b;",
+            "y = lim(f, x -> 0+) + lim(1/x, x -> infinity);",
+            "A = [[a, b], [c, d]] + [1, 2] + det(A) + A^T;",
+            "y = |x - a| + ||v|| + abs(|x|) + n! + (n!)! + binom(n, k);",
+            "x in [0, 1) union {1, 2} setminus {x | x > 0};",
+            "forall x in R: exists! y: x < y and not y = x;",
+            "p => q <=> not q => not p;",
+            "step = cases(1, x > 0; 0, otherwise);",
+            "v = d / t where t > 0 for \"all\" t;",
+            "y = \"if\" x > 0;",
+            "// a comment\ny = 1; /* trailing */\n\n\nz = 2;",
+        ] {
+            assert_idempotent(source);
+        }
+    }
+
+    #[test]
+    fn keeps_needed_parentheses_only() {
+        let functions = FunctionRegistry::default();
+        assert_eq!(
+            format_source("y=((a*b))+(c+d)*e;", &functions).unwrap(),
+            "y = a * b + (c + d) * e;\n"
+        );
+    }
+
+    #[test]
+    fn moved_comments_keep_blank_lines() {
+        let functions = FunctionRegistry::default();
+        assert_eq!(
+            format_source("/* multi\nline */\nz = (a +\n /* inner */ b);", &functions).unwrap(),
+            "/* multi\nline */\n/* inner */\nz = a + b;\n"
+        );
+        assert_eq!(
+            format_source("/* top */\n\nz = (a +\n /* inner */ b);", &functions).unwrap(),
+            "/* top */\n\n/* inner */\nz = a + b;\n"
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    rc::Rc,
+};
+
+use crate::error::Error;
+
+/// The number of arguments a function accepts, `max` is `None` when there is
+/// no upper limit.
+#[derive(Clone, Copy, Debug)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// Commands that already size the delimiter after them in a template.
+const SIZES: &[&str] = &[
+    "\\left", "\\right", "\\big", "\\bigl", "\\bigr", "\\Big", "\\Bigl", "\\Bigr", "\\bigg",
+    "\\biggl", "\\biggr", "\\Bigg", "\\Biggl", "\\Biggr",
+];
+
+/// A function that can be called from `.mvi` source.
+///
+/// The LaTeX template refers to the rendered arguments as `#1` to `#9`, and to
+/// all of them separated by commas as `#*`. Parentheses written in the
+/// template grow to fit arguments taller than a line. The MathML and Unicode
+/// templates use the same placeholders.
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: Arity,
+    pub latex: String,
+    /// Only LaTeX is rendered so far, these are kept for other outputs.
+    /// They come from `@function` declarations alone, the built-in
+    /// functions have none until one is declared for them.
+    #[allow(dead_code)]
+    pub mathml: Option<String>,
+    #[allow(dead_code)]
+    pub unicode: Option<String>,
+    /// The operator alone, e.g. `\sin`, for functions whose powers can be
+    /// written as `\sin^{2} x`.
+    pub operator: Option<String>,
+}
+
+impl Function {
+    pub fn render(&self, args: &[String], tall: bool) -> String {
+        let mut output = String::new();
+        let mut chars = self.latex.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('#', Some('*')) => {
+                    chars.next();
+                    output.push_str(&args.join(", "));
+                }
+                ('#', Some(n @ '1'..='9')) => {
+                    let index = *n as usize - '1' as usize;
+                    chars.next();
+                    output.push_str(args.get(index).map_or("", |arg| arg.as_str()));
+                }
+                ('(' | ')', _) if tall && !SIZES.iter().any(|size| output.ends_with(size)) => {
+                    output.push_str(if c == '(' { "\\left(" } else { "\\right)" })
+                }
+                _ => output.push(c),
+            }
+        }
+
+        output
+    }
+
+    /// Parses a declaration of the form `@function name/arity = template`,
+    /// where the arity is `n`, `n..m` or `n..` for no upper limit. The arity
+    /// may be followed by `mathml` or `unicode` to give that template instead
+    /// of the LaTeX one.
+    pub fn parse_declaration(text: &str) -> Result<Declaration, String> {
+        let rest = text
+            .trim()
+            .strip_prefix("@function")
+            .ok_or("declarations start with @function")?;
+        let (signature, template) = rest
+            .split_once('=')
+            .ok_or("expected \"=\" followed by a template")?;
+        let (signature, kind) = match signature.split_whitespace().collect::<Vec<_>>()[..] {
+            [signature] => (signature, TemplateKind::Latex),
+            [signature, "mathml"] => (signature, TemplateKind::MathMl),
+            [signature, "unicode"] => (signature, TemplateKind::Unicode),
+            [_, kind] => return Err(format!("\"{}\" is not a template kind", kind)),
+            _ => return Err("expected \"name/arity\"".to_string()),
+        };
+        let (name, arity) = signature.split_once('/').ok_or("expected \"name/arity\"")?;
+
+        if name.is_empty() || !name.chars().all(char::is_alphanumeric) {
+            return Err(format!("\"{}\" is not a valid function name", name));
+        }
+
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("\"{}\" is not a valid arity", arity))
+        };
+        let arity = match arity.split_once("..") {
+            Some((min, "")) => Arity {
+                min: parse(min)?,
+                max: None,
+            },
+            Some((min, max)) => Arity {
+                min: parse(min)?,
+                max: Some(parse(max)?),
+            },
+            None => Arity {
+                min: parse(arity)?,
+                max: Some(parse(arity)?),
+            },
+        };
+
+        Ok(Declaration {
+            name: name.to_string(),
+            arity,
+            kind,
+            template: template.trim().to_string(),
+        })
+    }
+}
+
+/// The output a function template is written for.
+#[derive(Clone, Copy, Debug)]
+pub enum TemplateKind {
+    Latex,
+    MathMl,
+    Unicode,
+}
+
+/// A single `@function` declaration, giving one template of a function.
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: String,
+    pub arity: Arity,
+    pub kind: TemplateKind,
+    pub template: String,
+}
+
+/// Name, minimum and maximum number of arguments, LaTeX template, and for
+/// functions conventionally raised as `\sin^{2} x` the operator on its own.
+/// Built-ins only carry LaTeX, MathML and Unicode templates are declared.
+type Builtin = (
+    &'static str,
+    usize,
+    Option<usize>,
+    &'static str,
+    Option<&'static str>,
+);
+
+const BUILTINS: &[Builtin] = &[
+    ("sqrt", 1, Some(1), "\\sqrt{#1}", None),
+    ("sqrt", 2, Some(2), "\\sqrt[#2]{#1}", None),
+    ("root", 2, Some(2), "\\sqrt[#1]{#2}", None),
+    ("sin", 1, Some(1), "\\sin(#1)", Some("\\sin")),
+    ("cos", 1, Some(1), "\\cos(#1)", Some("\\cos")),
+    ("tan", 1, Some(1), "\\tan(#1)", Some("\\tan")),
+    ("cot", 1, Some(1), "\\cot(#1)", Some("\\cot")),
+    ("sec", 1, Some(1), "\\sec(#1)", Some("\\sec")),
+    ("csc", 1, Some(1), "\\csc(#1)", Some("\\csc")),
+    ("arcsin", 1, Some(1), "\\arcsin(#1)", Some("\\arcsin")),
+    ("asin", 1, Some(1), "\\arcsin(#1)", Some("\\arcsin")),
+    ("arccos", 1, Some(1), "\\arccos(#1)", Some("\\arccos")),
+    ("acos", 1, Some(1), "\\arccos(#1)", Some("\\arccos")),
+    ("arctan", 1, Some(1), "\\arctan(#1)", Some("\\arctan")),
+    ("atan", 1, Some(1), "\\arctan(#1)", Some("\\arctan")),
+    ("sinh", 1, Some(1), "\\sinh(#1)", Some("\\sinh")),
+    ("cosh", 1, Some(1), "\\cosh(#1)", Some("\\cosh")),
+    ("tanh", 1, Some(1), "\\tanh(#1)", Some("\\tanh")),
+    ("coth", 1, Some(1), "\\coth(#1)", Some("\\coth")),
+    (
+        "arsinh",
+        1,
+        Some(1),
+        "\\operatorname{arsinh}(#1)",
+        Some("\\operatorname{arsinh}"),
+    ),
+    (
+        "asinh",
+        1,
+        Some(1),
+        "\\operatorname{arsinh}(#1)",
+        Some("\\operatorname{arsinh}"),
+    ),
+    (
+        "arcosh",
+        1,
+        Some(1),
+        "\\operatorname{arcosh}(#1)",
+        Some("\\operatorname{arcosh}"),
+    ),
+    (
+        "acosh",
+        1,
+        Some(1),
+        "\\operatorname{arcosh}(#1)",
+        Some("\\operatorname{arcosh}"),
+    ),
+    (
+        "artanh",
+        1,
+        Some(1),
+        "\\operatorname{artanh}(#1)",
+        Some("\\operatorname{artanh}"),
+    ),
+    (
+        "atanh",
+        1,
+        Some(1),
+        "\\operatorname{artanh}(#1)",
+        Some("\\operatorname{artanh}"),
+    ),
+    ("log", 1, Some(1), "\\log(#1)", Some("\\log")),
+    ("log", 2, Some(2), "\\log_{#2}(#1)", None),
+    ("ln", 1, Some(1), "\\ln(#1)", Some("\\ln")),
+    ("exp", 1, Some(1), "\\exp(#1)", None),
+    (
+        "floor",
+        1,
+        Some(1),
+        "\\left\\lfloor #1 \\right\\rfloor",
+        None,
+    ),
+    ("ceil", 1, Some(1), "\\left\\lceil #1 \\right\\rceil", None),
+    ("sign", 1, Some(1), "\\operatorname{sgn}(#1)", None),
+    ("sgn", 1, Some(1), "\\operatorname{sgn}(#1)", None),
+    ("min", 2, None, "\\min(#*)", None),
+    ("max", 2, None, "\\max(#*)", None),
+    ("gcd", 2, None, "\\gcd(#*)", None),
+    ("lcm", 2, None, "\\operatorname{lcm}(#*)", None),
+    ("perm", 2, Some(2), "{}^{#1}P_{#2}", None),
+    ("falling", 2, Some(2), "{#1}^{\\underline{#2}}", None),
+    ("rising", 2, Some(2), "{#1}^{\\overline{#2}}", None),
+    ("det", 1, Some(1), "\\det(#1)", None),
+    ("transpose", 1, Some(1), "{#1}^{\\mathsf{T}}", None),
+    ("inv", 1, Some(1), "{#1}^{-1}", None),
+    ("tr", 1, Some(1), "\\operatorname{tr}(#1)", None),
+    ("rank", 1, Some(1), "\\operatorname{rank}(#1)", None),
+];
+
+/// Every function known to the parser. A name may be declared several times
+/// with different arities, e.g. `log/1` and `log/2`, the last matching
+/// declaration wins.
+///
+/// Names without a template, `f`, `g` and `h` unless changed, are applied to
+/// their arguments as in `f(x)` rather than multiplied with them.
+#[derive(Clone, Debug)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Vec<Rc<Function>>>,
+    applied: HashSet<String>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            functions: HashMap::new(),
+            applied: ["f", "g", "h"].map(str::to_string).into(),
+        };
+        for (name, min, max, latex, operator) in BUILTINS {
+            registry.declare(Function {
+                name: name.to_string(),
+                arity: Arity {
+                    min: *min,
+                    max: *max,
+                },
+                latex: latex.to_string(),
+                mathml: None,
+                unicode: None,
+                operator: operator.map(str::to_string),
+            });
+        }
+        registry
+    }
+}
+
+impl FunctionRegistry {
+    pub fn declare(&mut self, function: Function) {
+        self.functions
+            .entry(function.name.clone())
+            .or_default()
+            .push(Rc::new(function));
+    }
+
+    /// Declares a function from `@function name/arity = template`, or a
+    /// name that is applied to its arguments from `@function name`. MathML
+    /// and Unicode templates are added to the function declared with the
+    /// same arity before them.
+    pub fn declare_line(&mut self, text: &str) -> Result<(), String> {
+        if let Some(name) = text.trim().strip_prefix("@function").map(str::trim) {
+            if !name.is_empty() && name.chars().all(char::is_alphanumeric) {
+                self.applied.insert(name.to_string());
+                return Ok(());
+            }
+        }
+
+        let declaration = Function::parse_declaration(text)?;
+        let template = declaration.template;
+        if let TemplateKind::Latex = declaration.kind {
+            self.declare(Function {
+                name: declaration.name,
+                arity: declaration.arity,
+                latex: template,
+                mathml: None,
+                unicode: None,
+                operator: None,
+            });
+            return Ok(());
+        }
+
+        let function = self
+            .functions
+            .get_mut(&declaration.name)
+            .and_then(|overloads| {
+                overloads.iter_mut().rev().find(|function| {
+                    (function.arity.min, function.arity.max)
+                        == (declaration.arity.min, declaration.arity.max)
+                })
+            })
+            .ok_or_else(|| {
+                format!(
+                    "declare the LaTeX template of \"{}\" taking {} argument(s) first",
+                    declaration.name, declaration.arity
+                )
+            })?;
+        let function = Rc::make_mut(function);
+        match declaration.kind {
+            TemplateKind::MathMl => function.mathml = Some(template),
+            TemplateKind::Unicode => function.unicode = Some(template),
+            TemplateKind::Latex => unreachable!("LaTeX templates are declared above"),
+        }
+        Ok(())
+    }
+
+    /// Declares every `@function` line of a functions file, other lines must
+    /// be empty or comments starting with `#` or `//`.
+    pub fn load(&mut self, source: &str) -> Result<(), Error> {
+        for (line, text) in source.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') || text.starts_with("//") {
+                continue;
+            }
+            self.declare_line(text)
+                .map_err(|message| Error::InvalidDeclaration { line, message })?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the names applied to their arguments, `f`, `g` and `h` by
+    /// default.
+    pub fn set_applied(&mut self, names: &[String]) {
+        self.applied = names.iter().cloned().collect();
+    }
+
+    pub fn is_applied(&self, name: &str) -> bool {
+        self.applied.contains(name)
+    }
+
+    /// Whether `name` refers to a function.
+    pub fn contains(&self, name: &str) -> bool {
+        self.overloads(name).is_some()
+    }
+
+    /// The declaration of `name` taking `nr_of_args` arguments.
+    pub fn get(&self, name: &str, nr_of_args: usize) -> Option<Rc<Function>> {
+        self.overloads(name)?
+            .iter()
+            .rev()
+            .find(|function| function.arity.accepts(nr_of_args))
+            .cloned()
+    }
+
+    /// All the arities `name` was declared with, for error messages.
+    pub fn arity(&self, name: &str) -> Option<Arity> {
+        let overloads = self.overloads(name)?;
+        let min = overloads.iter().map(|function| function.arity.min).min()?;
+        let max = overloads
+            .iter()
+            .map(|function| function.arity.max)
+            .try_fold(0, |max, n| n.map(|n| n.max(max)));
+        Some(Arity { min, max })
+    }
+
+    fn overloads(&self, name: &str) -> Option<&Vec<Rc<Function>>> {
+        self.functions.get(name)
+    }
+}
@@ -1,11 +1,12 @@
-use core::panic;
 use std::{fmt::Display, iter::Peekable, str::Chars};
 
-// !TODO : UNIT TESTS
+use crate::error::Error;
+use crate::number::Number;
+use crate::symbols;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
-    Number(f64),
+    Number(Number),
     Identifier(String),
     Plus,
     Minus,
@@ -18,6 +19,28 @@ pub enum TokenKind {
     End,
     Dot,
     Comma,
+    Underscore,
+    BraceOpen,
+    BraceClose,
+    Prime,
+    Arrow,
+    BracketOpen,
+    BracketClose,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    NotEquals,
+    Approx,
+    Tilde,
+    Equiv,
+    Propto,
+    Pipe,
+    Colon,
+    Bang,
+    Implies,
+    Iff,
+    Text(String),
 }
 
 impl Display for TokenKind {
@@ -36,6 +59,28 @@ impl Display for TokenKind {
             TokenKind::End => write!(f, ";"),
             TokenKind::Dot => write!(f, "."),
             TokenKind::Comma => write!(f, ","),
+            TokenKind::Underscore => write!(f, "_"),
+            TokenKind::BraceOpen => write!(f, "{{"),
+            TokenKind::BraceClose => write!(f, "}}"),
+            TokenKind::Prime => write!(f, "'"),
+            TokenKind::Arrow => write!(f, "->"),
+            TokenKind::BracketOpen => write!(f, "["),
+            TokenKind::BracketClose => write!(f, "]"),
+            TokenKind::Less => write!(f, "<"),
+            TokenKind::LessEquals => write!(f, "<="),
+            TokenKind::Greater => write!(f, ">"),
+            TokenKind::GreaterEquals => write!(f, ">="),
+            TokenKind::NotEquals => write!(f, "!="),
+            TokenKind::Approx => write!(f, "~~"),
+            TokenKind::Tilde => write!(f, "~"),
+            TokenKind::Equiv => write!(f, "-="),
+            TokenKind::Propto => write!(f, "∝"),
+            TokenKind::Pipe => write!(f, "|"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Bang => write!(f, "!"),
+            TokenKind::Implies => write!(f, "=>"),
+            TokenKind::Iff => write!(f, "<=>"),
+            TokenKind::Text(a) => write!(f, "\"{}\"", a.replace('\\', "\\\\").replace('"', "\\\"")),
         }
     }
 }
@@ -43,21 +88,48 @@ impl Display for TokenKind {
 impl TokenKind {
     pub fn takes_precedence(&self, precidence: usize) -> bool {
         match &self {
-            TokenKind::Plus => precidence == 1,
-            TokenKind::Minus => precidence == 1,
-            TokenKind::Multiply => precidence == 2,
-            TokenKind::Divider => precidence == 2,
-            TokenKind::Power => precidence == 3,
-            TokenKind::Equals => precidence == 0,
+            TokenKind::Iff => precidence == 0,
+            TokenKind::Implies => precidence == 1,
+            TokenKind::Plus => precidence == 6,
+            TokenKind::Minus => precidence == 6,
+            TokenKind::Multiply => precidence == 7,
+            TokenKind::Divider => precidence == 7,
+            TokenKind::Power => precidence == 8,
+            TokenKind::Identifier(name) => match name.as_str() {
+                "or" | "xor" => precidence == 2,
+                "and" => precidence == 3,
+                "union" | "setminus" => precidence == 6,
+                "intersection" | "choose" => precidence == 7,
+                _ => false,
+            },
             _ => false,
         }
     }
 }
 
-#[derive(Debug)]
+/// Operators written as words in `.mvi` source, along with the Unicode
+/// character that may be used instead. The character lexes as the word.
+const OPERATOR_CHARS: &[(char, &str)] = &[
+    ('∪', "union"),
+    ('∩', "intersection"),
+    ('∖', "setminus"),
+    ('∈', "in"),
+    ('∉', "notin"),
+    ('⊆', "subset"),
+    ('⊇', "supset"),
+    ('∧', "and"),
+    ('∨', "or"),
+    ('¬', "not"),
+    ('⊕', "xor"),
+    ('∀', "forall"),
+    ('∃', "exists"),
+];
+
+#[derive(Clone, Debug)]
 pub struct Token {
     pub line: usize,
     pub col: usize,
+    #[allow(dead_code)]
     pub size: usize,
     pub kind: TokenKind,
 }
@@ -77,20 +149,84 @@ impl Token {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommentKind {
+    /// `// ...` until the end of the line
+    Slash,
+    /// `# ...` until the end of the line
+    Hash,
+    /// `/* ... */`, possibly spanning several lines
+    Block,
+}
+
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// Everything between the delimiters, as written.
+    pub text: String,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+}
+
+impl Display for Comment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            CommentKind::Slash => write!(f, "//{}", self.text),
+            CommentKind::Hash => write!(f, "#{}", self.text),
+            CommentKind::Block => write!(f, "/*{}*/", self.text),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
     line: usize,
     col: usize,
+    comments: Vec<Comment>,
+    /// The problem that stopped the lexer early, if any.
+    error: Option<LexError>,
+}
+
+#[derive(Clone, Debug)]
+enum LexError {
+    /// What the source ended in the middle of, and where it started.
+    Unterminated(&'static str, usize, usize),
+    /// A literal with digits outside its radix, like `0b12`.
+    InvalidNumber(String, usize, usize),
+    /// A character that starts no token, like `@`.
+    UnexpectedChar(char, usize, usize),
 }
 
 impl<'a> Lexer<'a> {
     pub fn new<S: AsRef<str>>(text: &'a S) -> Self {
-        return Self {
+        Self {
             chars: text.as_ref().chars().peekable(),
             line: 0,
             col: 0,
-        };
+            comments: vec![],
+            error: None,
+        }
+    }
+
+    /// Runs the lexer to the end, returning every comment it skipped, or an
+    /// error if the source ends inside one, holds an invalid literal or a
+    /// character that starts no token.
+    pub fn comments(mut self) -> Result<Vec<Comment>, Error> {
+        for _ in self.by_ref() {}
+        match self.error {
+            Some(LexError::Unterminated(what, line, col)) => {
+                Err(Error::Unterminated { line, col, what })
+            }
+            Some(LexError::InvalidNumber(text, line, col)) => {
+                Err(Error::InvalidNumber { line, col, text })
+            }
+            Some(LexError::UnexpectedChar(found, line, col)) => {
+                Err(Error::UnexpectedChar { line, col, found })
+            }
+            None => Ok(self.comments),
+        }
     }
 
     fn peek(&mut self) -> Option<&char> {
@@ -111,38 +247,148 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    fn parse_comment(&mut self, kind: CommentKind) {
+        let (line, col) = (self.line, self.col);
+        let mut text = String::new();
+
+        match kind {
+            CommentKind::Slash | CommentKind::Hash => {
+                while let Some(c) = self.peek() {
+                    if *c == '\n' {
+                        break;
+                    }
+                    text.push(*c);
+                    self.next();
+                }
+                text.truncate(text.trim_end().len());
+            }
+            CommentKind::Block => loop {
+                match self.next() {
+                    Some('*') if self.peek() == Some(&'/') => {
+                        self.next();
+                        break;
+                    }
+                    Some(c) => text.push(c),
+                    None => {
+                        self.error = Some(LexError::Unterminated("Comment", line, col));
+                        break;
+                    }
+                }
+            },
+        }
+
+        self.comments.push(Comment {
+            kind,
+            text,
+            line,
+            col,
+            end_line: self.line,
+        });
+    }
+
     fn parse_ident(&mut self, start: char) -> Option<Token> {
         let mut string = String::from(start);
         while let Some(c) = self.peek() {
-            if c.is_alphanumeric() {
-                string.push(c.clone());
+            if (c.is_alphabetic() || c.is_ascii_digit()) && symbols::by_char(*c).is_none() {
+                string.push(*c);
                 self.next();
             } else {
                 break;
             }
         }
         // fixme size
-        return Token::some(TokenKind::Identifier(string), self.line, self.col, 0);
+        Token::some(TokenKind::Identifier(string), self.line, self.col, 0)
     }
 
-    fn parse_num(&mut self, start: char) -> Option<Token> {
-        let mut string = String::from(start);
-        while let Some(c) = self.peek() {
-            if c.is_numeric() || *c == '.' {
-                string.push(c.clone());
+    /// Reads a quoted string up to its closing `"`, where `\"` and `\\` stand
+    /// for a quote and a backslash.
+    fn parse_text(&mut self) -> Option<Token> {
+        let (line, col) = (self.line, self.col);
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => break,
+                Some('\\') if matches!(self.peek(), Some('"' | '\\')) => text.extend(self.next()),
+                Some(c) => text.push(c),
+                None => {
+                    self.error = Some(LexError::Unterminated("String", line, col));
+                    return None;
+                }
+            }
+        }
+        // fixme size
+        Token::some(TokenKind::Text(text), self.line, self.col, 0)
+    }
+
+    /// The character after the next one.
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next()
+    }
+
+    /// Reads digits in `radix`, along with any `_` that separates two of them.
+    fn parse_digits(&mut self, string: &mut String, radix: u32) {
+        while let Some(&c) = self.peek() {
+            let separator = c == '_' && self.peek_second().is_some_and(|c| c.is_digit(radix));
+            if c.is_digit(radix) || separator {
+                string.push(c);
                 self.next();
             } else {
                 break;
             }
         }
+    }
+
+    fn parse_num(&mut self, start: char) -> Option<Token> {
+        let mut string = String::from(start);
+        let radix = match (start, self.peek().copied(), self.peek_second()) {
+            ('0', Some('x' | 'X'), Some(c)) if c.is_ascii_hexdigit() => 16,
+            ('0', Some('b' | 'B'), Some('0' | '1')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            string.extend(self.next());
+        }
+        self.parse_digits(&mut string, radix);
+
+        // `0b12` is a mistake rather than `0b1` times 2.
+        if radix != 10 && self.peek().is_some_and(|c| c.is_alphanumeric()) {
+            while let Some(&c) = self.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                string.push(c);
+                self.next();
+            }
+            self.error = Some(LexError::InvalidNumber(string, self.line, self.col));
+            return None;
+        }
+
+        if radix == 10 {
+            if self.peek() == Some(&'.') && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+                string.extend(self.next());
+                self.parse_digits(&mut string, 10);
+            }
+            // `e` only starts an exponent when digits follow, so `2e^x` is
+            // still two times e.
+            let mut exponent = self.chars.clone();
+            if exponent.next_if(|c| matches!(c, 'e' | 'E')).is_some() {
+                let sign = exponent.next_if(|c| matches!(c, '+' | '-')).is_some();
+                if exponent.peek().is_some_and(char::is_ascii_digit) {
+                    string.extend(self.next());
+                    if sign {
+                        string.extend(self.next());
+                    }
+                    self.parse_digits(&mut string, 10);
+                }
+            }
+        }
 
         // fixme size
-        return Token::some(
-            TokenKind::Number(string.parse().expect("Unreachable")),
+        Token::some(
+            TokenKind::Number(Number::new(string)),
             self.line,
             self.col,
             0,
-        );
+        )
     }
 }
 
@@ -153,6 +399,15 @@ impl<'a> Iterator for Lexer<'a> {
         let current = loop {
             match self.next() {
                 None => return None,
+                Some('#') => self.parse_comment(CommentKind::Hash),
+                Some('/') if self.peek() == Some(&'/') => {
+                    self.next();
+                    self.parse_comment(CommentKind::Slash);
+                }
+                Some('/') if self.peek() == Some(&'*') => {
+                    self.next();
+                    self.parse_comment(CommentKind::Block);
+                }
                 Some(c) if !c.is_whitespace() => break c,
                 _ => {}
             }
@@ -160,19 +415,153 @@ impl<'a> Iterator for Lexer<'a> {
 
         match current {
             '+' => Token::some(TokenKind::Plus, self.line, self.col, 1),
+            '-' if self.peek() == Some(&'>') => {
+                self.next();
+                Token::some(TokenKind::Arrow, self.line, self.col - 1, 2)
+            }
+            '→' => Token::some(TokenKind::Arrow, self.line, self.col, 1),
+            '-' if self.peek() == Some(&'=') => {
+                self.next();
+                Token::some(TokenKind::Equiv, self.line, self.col - 1, 2)
+            }
             '-' => Token::some(TokenKind::Minus, self.line, self.col, 1),
             '*' => Token::some(TokenKind::Multiply, self.line, self.col, 1),
             '/' => Token::some(TokenKind::Divider, self.line, self.col, 1),
             '^' => Token::some(TokenKind::Power, self.line, self.col, 1),
             '(' => Token::some(TokenKind::ParenOpen, self.line, self.col, 1),
             ')' => Token::some(TokenKind::ParenClose, self.line, self.col, 1),
+            '=' if self.peek() == Some(&'>') => {
+                self.next();
+                Token::some(TokenKind::Implies, self.line, self.col - 1, 2)
+            }
             '=' => Token::some(TokenKind::Equals, self.line, self.col, 1),
+            '<' if self.peek() == Some(&'=') => {
+                self.next();
+                if self.peek() == Some(&'>') {
+                    self.next();
+                    Token::some(TokenKind::Iff, self.line, self.col - 2, 3)
+                } else {
+                    Token::some(TokenKind::LessEquals, self.line, self.col - 1, 2)
+                }
+            }
+            '>' if self.peek() == Some(&'=') => {
+                self.next();
+                Token::some(TokenKind::GreaterEquals, self.line, self.col - 1, 2)
+            }
+            '!' if self.peek() == Some(&'=') => {
+                self.next();
+                Token::some(TokenKind::NotEquals, self.line, self.col - 1, 2)
+            }
+            '~' if self.peek() == Some(&'~') => {
+                self.next();
+                Token::some(TokenKind::Approx, self.line, self.col - 1, 2)
+            }
+            '<' => Token::some(TokenKind::Less, self.line, self.col, 1),
+            '>' => Token::some(TokenKind::Greater, self.line, self.col, 1),
+            '~' | '∼' => Token::some(TokenKind::Tilde, self.line, self.col, 1),
+            '≤' => Token::some(TokenKind::LessEquals, self.line, self.col, 1),
+            '≥' => Token::some(TokenKind::GreaterEquals, self.line, self.col, 1),
+            '≠' => Token::some(TokenKind::NotEquals, self.line, self.col, 1),
+            '≈' => Token::some(TokenKind::Approx, self.line, self.col, 1),
+            '≡' => Token::some(TokenKind::Equiv, self.line, self.col, 1),
+            '∝' => Token::some(TokenKind::Propto, self.line, self.col, 1),
+            '|' => Token::some(TokenKind::Pipe, self.line, self.col, 1),
+            ':' => Token::some(TokenKind::Colon, self.line, self.col, 1),
+            '!' => Token::some(TokenKind::Bang, self.line, self.col, 1),
+            '⇒' => Token::some(TokenKind::Implies, self.line, self.col, 1),
+            '⇔' => Token::some(TokenKind::Iff, self.line, self.col, 1),
             ';' => Token::some(TokenKind::End, self.line, self.col, 1),
             ',' => Token::some(TokenKind::Comma, self.line, self.col, 1),
             '.' => Token::some(TokenKind::Dot, self.line, self.col, 1),
-            c @ ('_' | 'a'..='z' | 'A'..='Z') => self.parse_ident(c),
+            '_' => Token::some(TokenKind::Underscore, self.line, self.col, 1),
+            '{' => Token::some(TokenKind::BraceOpen, self.line, self.col, 1),
+            '}' => Token::some(TokenKind::BraceClose, self.line, self.col, 1),
+            '[' => Token::some(TokenKind::BracketOpen, self.line, self.col, 1),
+            ']' => Token::some(TokenKind::BracketClose, self.line, self.col, 1),
+            '\'' => Token::some(TokenKind::Prime, self.line, self.col, 1),
+            c @ ('a'..='z' | 'A'..='Z') => self.parse_ident(c),
             c @ '0'..='9' => self.parse_num(c),
-            _ => panic!("UNDEFINED TOKEN : {}", current),
+            '"' => self.parse_text(),
+            c if OPERATOR_CHARS.iter().any(|(op, _)| *op == c) => {
+                let (_, name) = OPERATOR_CHARS
+                    .iter()
+                    .find(|(op, _)| *op == c)
+                    .expect("checked by the guard");
+                Token::some(
+                    TokenKind::Identifier(name.to_string()),
+                    self.line,
+                    self.col,
+                    1,
+                )
+            }
+            // Unicode letters and constants from the symbol table stand alone
+            c if symbols::by_char(c).is_some() => {
+                Token::some(TokenKind::Identifier(c.to_string()), self.line, self.col, 1)
+            }
+            c if c.is_alphabetic() => self.parse_ident(c),
+            c => {
+                self.error = Some(LexError::UnexpectedChar(c, self.line, self.col));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The kinds of the tokens in `source`.
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        Lexer::new(&source).map(|token| token.kind).collect()
+    }
+
+    fn number(text: &str) -> TokenKind {
+        TokenKind::Number(Number::new(text.to_string()))
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(kinds("1_000"), [number("1_000")]);
+        assert_eq!(Number::new("1_000".to_string()).value, 1000.0);
+        assert_eq!(kinds("6.02e23"), [number("6.02e23")]);
+        assert_eq!(kinds("0xFF"), [number("0xFF")]);
+        assert_eq!(Number::new("0b1010".to_string()).value, 10.0);
+    }
+
+    #[test]
+    fn exponent_needs_digits() {
+        // `2e^x` is 2 times e to the x, not a literal with an exponent.
+        assert_eq!(
+            kinds("2e^x"),
+            [
+                number("2"),
+                TokenKind::Identifier("e".to_string()),
+                TokenKind::Power,
+                TokenKind::Identifier("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert!(matches!(
+            Lexer::new(&"0b12").comments(),
+            Err(Error::InvalidNumber { text, .. }) if text == "0b12"
+        ));
+        assert!(matches!(
+            Lexer::new(&"0xFG").comments(),
+            Err(Error::InvalidNumber { .. })
+        ));
+    }
+
+    #[test]
+    fn unexpected_characters() {
+        for source in ["a @ b", "a − b", "x²"] {
+            assert!(matches!(
+                Lexer::new(&source).comments(),
+                Err(Error::UnexpectedChar { .. })
+            ));
         }
     }
 }
@@ -0,0 +1,426 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::error::Error;
+use crate::expression::{BinaryOp, Expr, RelationOp, UnaryOp};
+use crate::format::format_expr;
+use crate::parser::Statement;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+enum Check {
+    /// Runs on every node of every statement.
+    Node(fn(&Expr) -> Option<String>),
+    /// Runs once over the whole file, reporting `(statement index, message)`.
+    File(fn(&[Statement]) -> Vec<(usize, String)>),
+}
+
+pub struct Rule {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    check: Check,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        code: "L001",
+        name: "ambiguous-division",
+        severity: Severity::Warning,
+        check: Check::Node(ambiguous_division),
+    },
+    Rule {
+        code: "L002",
+        name: "unary-minus-power",
+        severity: Severity::Warning,
+        check: Check::Node(unary_minus_power),
+    },
+    Rule {
+        code: "L003",
+        name: "power-tower",
+        severity: Severity::Warning,
+        check: Check::Node(power_tower),
+    },
+    Rule {
+        code: "L004",
+        name: "duplicate-equation",
+        severity: Severity::Warning,
+        check: Check::File(duplicate_equation),
+    },
+    Rule {
+        code: "L005",
+        name: "unused-definition",
+        severity: Severity::Info,
+        check: Check::File(unused_definition),
+    },
+    Rule {
+        code: "L006",
+        name: "misplaced-dots",
+        severity: Severity::Warning,
+        check: Check::File(misplaced_dots),
+    },
+];
+
+impl Rule {
+    pub fn find(code_or_name: &str) -> Result<&'static Rule, Error> {
+        RULES
+            .iter()
+            .find(|rule| rule.matches(code_or_name))
+            .ok_or_else(|| Error::UnknownLintRule(code_or_name.to_string()))
+    }
+
+    fn matches(&self, code_or_name: &str) -> bool {
+        self.code.eq_ignore_ascii_case(code_or_name) || self.name == code_or_name
+    }
+}
+
+pub struct Diagnostic {
+    pub rule: &'static Rule,
+    pub severity: Severity,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}] {}: {}",
+            self.line + 1,
+            self.severity,
+            self.rule.code,
+            self.rule.name,
+            self.message
+        )
+    }
+}
+
+/// Overrides the default severity of rules, `None` turns a rule off.
+#[derive(Default)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Option<Severity>>,
+}
+
+impl LintConfig {
+    pub fn set(&mut self, rule: &str, level: Option<Severity>) -> Result<(), Error> {
+        self.levels.insert(Rule::find(rule)?.code, level);
+        Ok(())
+    }
+
+    fn level(&self, rule: &Rule) -> Option<Severity> {
+        match self.levels.get(rule.code) {
+            Some(level) => *level,
+            None => Some(rule.severity),
+        }
+    }
+}
+
+pub fn lint(statements: &[Statement], config: &LintConfig) -> Vec<Diagnostic> {
+    let mut output = vec![];
+
+    for rule in RULES {
+        let Some(severity) = config.level(rule) else {
+            continue;
+        };
+        let found = match rule.check {
+            Check::Node(check) => {
+                let mut found = vec![];
+                for (index, statement) in statements.iter().enumerate() {
+                    walk(&statement.expr, &mut |expr| {
+                        if let Some(message) = check(expr) {
+                            found.push((index, message));
+                        }
+                    });
+                }
+                found
+            }
+            Check::File(check) => check(statements),
+        };
+
+        output.extend(
+            found
+                .into_iter()
+                .filter(|(index, _)| !is_suppressed(&statements[*index], rule))
+                .map(|(index, message)| Diagnostic {
+                    rule,
+                    severity,
+                    line: statements[index].line,
+                    message,
+                }),
+        );
+    }
+
+    output.sort_by_key(|diagnostic| diagnostic.line);
+    output
+}
+
+/// Whether a comment on the statement reads `lint: allow(<rule>, ...)`.
+fn is_suppressed(statement: &Statement, rule: &Rule) -> bool {
+    statement
+        .comments
+        .iter()
+        .chain(&statement.trailing)
+        .any(|comment| {
+            let Some(rules) = comment
+                .text
+                .trim()
+                .strip_prefix("lint: allow(")
+                .and_then(|rules| rules.strip_suffix(')'))
+            else {
+                return false;
+            };
+            rules
+                .split(',')
+                .map(str::trim)
+                .any(|name| rule.matches(name))
+        })
+}
+
+fn walk<'a>(expr: &'a Expr, visit: &mut impl FnMut(&'a Expr)) {
+    visit(expr);
+    for child in expr.children() {
+        walk(child, visit);
+    }
+}
+
+fn ambiguous_division(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Binary(a, _, BinaryOp::Mult | BinaryOp::Frac)
+            if matches!(**a, Expr::Binary(_, _, BinaryOp::Frac)) =>
+        {
+            Some(format!(
+                "\"{}\" can be read with either division first, add parentheses",
+                format_expr(expr)
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn unary_minus_power(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Unary(a, UnaryOp::Sub) if matches!(**a, Expr::Binary(_, _, BinaryOp::Pow)) => {
+            Some(format!(
+                "\"{}\" negates the whole power, add parentheses to make that explicit",
+                format_expr(expr)
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn power_tower(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Binary(_, b, BinaryOp::Pow) if matches!(**b, Expr::Binary(_, _, BinaryOp::Pow)) => {
+            Some(format!(
+                "\"{}\" is evaluated from the right, add parentheses to the exponent",
+                format_expr(expr)
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn duplicate_equation(statements: &[Statement]) -> Vec<(usize, String)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut output = vec![];
+
+    for (index, statement) in statements.iter().enumerate() {
+        let text = format_expr(&statement.expr);
+        if let Some(first) = seen.get(&text) {
+            output.push((
+                index,
+                format!(
+                    "\"{}\" was already written on line {}",
+                    text,
+                    statements[*first].line + 1
+                ),
+            ));
+        } else {
+            seen.insert(text, index);
+        }
+    }
+
+    output
+}
+
+fn unused_definition(statements: &[Statement]) -> Vec<(usize, String)> {
+    let mut output = vec![];
+
+    for (index, statement) in statements.iter().enumerate() {
+        let Expr::Relation(lhs, rest) = &statement.expr else {
+            continue;
+        };
+        if !matches!(rest[..], [(RelationOp::Eq, _)]) {
+            continue;
+        }
+        let Expr::Sym(name) = lhs.ungrouped() else {
+            continue;
+        };
+
+        let used = statements
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .any(|(_, other)| {
+                let mut found = false;
+                walk(&other.expr, &mut |expr| {
+                    if matches!(expr, Expr::Sym(c) | Expr::Apply(c, _) if c == name) {
+                        found = true;
+                    }
+                });
+                found
+            });
+
+        if !used {
+            output.push((index, format!("\"{}\" is defined but never used", name)));
+        }
+    }
+
+    output
+}
+
+fn misplaced_dots(statements: &[Statement]) -> Vec<(usize, String)> {
+    fn check(expr: &Expr, allowed: bool, found: &mut bool) {
+        if matches!(expr, Expr::Dots()) && !allowed {
+            *found = true;
+        }
+        let allowed = matches!(
+            expr,
+            Expr::Binary(_, _, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mult)
+                | Expr::Set(_)
+                | Expr::Matrix(_)
+                | Expr::Func(_, _)
+                | Expr::Apply(_, _)
+        );
+        for child in expr.children() {
+            check(child, allowed, found);
+        }
+    }
+
+    let mut output = vec![];
+    for (index, statement) in statements.iter().enumerate() {
+        let mut found = false;
+        check(&statement.expr, false, &mut found);
+        if found {
+            output.push((
+                index,
+                "\"...\" should only continue a sum, product or list".to_string(),
+            ));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::FunctionRegistry;
+    use crate::parser::parse_document;
+
+    /// The messages `rule` reports for `source` with the default levels.
+    fn messages(source: &str, rule: &str) -> Vec<String> {
+        let (statements, _) = parse_document(source, &FunctionRegistry::default()).unwrap();
+        lint(&statements, &LintConfig::default())
+            .into_iter()
+            .filter(|diagnostic| diagnostic.rule.matches(rule))
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn ambiguous_division() {
+        assert_eq!(messages("y = a/b*c;", "L001").len(), 1);
+        assert_eq!(messages("y = a/b/c;", "L001").len(), 1);
+        assert!(messages("y = (a/b)*c;", "L001").is_empty());
+        assert!(messages("y = a/(b*c);", "L001").is_empty());
+    }
+
+    #[test]
+    fn unary_minus_power() {
+        assert_eq!(messages("y = -x^2;", "L002").len(), 1);
+        assert!(messages("y = (-x)^2;", "L002").is_empty());
+        assert!(messages("y = -(x^2);", "L002").is_empty());
+    }
+
+    #[test]
+    fn power_tower() {
+        assert_eq!(messages("y = 2^3^4;", "L003").len(), 1);
+        assert!(messages("y = 2^(3^4);", "L003").is_empty());
+        assert!(messages("y = (2^3)^4;", "L003").is_empty());
+    }
+
+    #[test]
+    fn duplicate_equation() {
+        let found = messages("y = a + b;\ny = a+b;", "L004");
+        assert_eq!(found, ["\"y = a + b\" was already written on line 1"]);
+        assert!(messages("y = a + b;\ny = a - b;", "L004").is_empty());
+    }
+
+    #[test]
+    fn unused_definition() {
+        let found = messages("x = 1;\ny = 2 * x;", "L005");
+        assert_eq!(found, ["\"y\" is defined but never used"]);
+        let found = messages("x = 1;\ny = (x);\nz = y;\nw = z;", "L005");
+        assert_eq!(found, ["\"w\" is defined but never used"]);
+    }
+
+    #[test]
+    fn unused_definition_counts_applied_names() {
+        let found = messages("f = x^2;\ny = f(2);", "L005");
+        assert_eq!(found, ["\"y\" is defined but never used"]);
+    }
+
+    #[test]
+    fn misplaced_dots() {
+        assert!(messages("y = a_1 + ... + a_n;", "L006").is_empty());
+        assert!(messages("y = a_1 * ... * a_n;", "L006").is_empty());
+        assert!(messages("A = {1, 2, ..., n};", "L006").is_empty());
+        assert!(messages("y = f(x_1, ..., x_n);", "L006").is_empty());
+        assert!(messages("v = [x_1, ..., x_n];", "L006").is_empty());
+        assert!(messages("y = max(a_1, ..., a_n);", "L006").is_empty());
+        assert_eq!(messages("y = ... / 2;", "L006").len(), 1);
+        assert_eq!(messages("y = (...)^2;", "L006").len(), 1);
+    }
+
+    #[test]
+    fn allow_comments_silence_a_statement() {
+        assert!(messages("b = -x^2; // lint: allow(unary-minus-power)", "L002").is_empty());
+        assert!(messages("// lint: allow(L002, L003)\nb = -x^2;", "L002").is_empty());
+        assert_eq!(
+            messages("b = -x^2; // lint: allow(power-tower)", "L002").len(),
+            1
+        );
+        // Only the statement with the comment is silenced.
+        let source = "a = -x^2; // lint: allow(L002)\nb = -x^2;";
+        assert_eq!(messages(source, "L002").len(), 1);
+    }
+
+    #[test]
+    fn config_changes_levels() {
+        let (statements, _) = parse_document("y = -x^2;", &FunctionRegistry::default()).unwrap();
+        let mut config = LintConfig::default();
+        config
+            .set("unary-minus-power", Some(Severity::Error))
+            .unwrap();
+        config.set("L005", None).unwrap();
+        let found = lint(&statements, &config);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert!(config.set("L999", None).is_err());
+    }
+}
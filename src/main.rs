@@ -4,24 +4,99 @@ use std::{
 };
 
 use clap::Parser;
-use cli_args::Cli;
-use lexer::Lexer;
+use cli_args::{Cli, Commands, FmtArgs, LintArgs};
+use functions::FunctionRegistry;
 
 mod cli_args;
 mod consts;
 mod error;
 mod expression;
+mod format;
+mod functions;
 mod lexer;
+mod lint;
+mod number;
 mod parser;
+mod symbols;
 
 // TODO: Add a way to change the color of the background and foreground
 // TODO: Add better logging and a better way to track progress
-// TODO: Allow for differential and integral equations
 // TODO: Make output argument optional
 // TODO: Allow for parsing a equation entered inline
 
+fn fmt(args: FmtArgs, functions: &FunctionRegistry) -> Result<(), error::Error> {
+    let mut unformatted = 0;
+
+    for path in &args.files {
+        let source = std::fs::read_to_string(path)?;
+        let formatted = format::format_source(&source, functions)?;
+        if formatted == source {
+            continue;
+        }
+
+        if args.check {
+            eprintln!("Not formatted: {}", path.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(path, formatted)?;
+        }
+    }
+
+    if unformatted > 0 {
+        return Err(error::Error::Unformatted(unformatted));
+    }
+    Ok(())
+}
+
+fn lint(args: LintArgs, functions: &FunctionRegistry) -> Result<(), error::Error> {
+    let mut config = lint::LintConfig::default();
+    for rule in &args.allow {
+        config.set(rule, None)?;
+    }
+    for rule in &args.warn {
+        config.set(rule, Some(lint::Severity::Warning))?;
+    }
+    for rule in &args.deny {
+        config.set(rule, Some(lint::Severity::Error))?;
+    }
+
+    let mut errors = 0;
+    for path in &args.files {
+        let source = std::fs::read_to_string(path)?;
+        let (statements, _) = parser::parse_document(&source, functions)?;
+
+        for diagnostic in lint::lint(&statements, &config) {
+            if diagnostic.severity == lint::Severity::Error {
+                errors += 1;
+            }
+            eprintln!("{}:{}", path.display(), diagnostic);
+        }
+    }
+
+    if errors > 0 {
+        return Err(error::Error::LintFailed(errors));
+    }
+    Ok(())
+}
+
 fn run() -> Result<(), error::Error> {
     let args = Cli::parse();
+
+    let mut functions = FunctionRegistry::default();
+    if let Some(names) = &args.function_names {
+        functions.set_applied(names);
+    }
+    for path in &args.functions {
+        functions.load(&std::fs::read_to_string(path)?)?;
+    }
+
+    match args.command {
+        Some(Commands::Fmt(fmt_args)) => return fmt(fmt_args, &functions),
+        Some(Commands::Lint(lint_args)) => return lint(lint_args, &functions),
+        None => {}
+    }
+
+    let output_file = args.output_file.expect("output file is required");
     let input_string = if let Some(str) = args.string {
         str
     } else if let Some(input_file) = args.input_file {
@@ -30,22 +105,27 @@ fn run() -> Result<(), error::Error> {
         unreachable!();
     };
 
-    let lexer = Lexer::new(&input_string);
-
-    let mut parser = parser::Parser::new(lexer.peekable());
-
-    let expressions = parser.parse_all()?;
+    let (statements, _) = parser::parse_document(&input_string, &functions)?;
+    let style = expression::Style {
+        identifiers: args.identifiers,
+        function_powers: args.function_powers,
+        matrices: args.matrices,
+        multiplication: args.multiplication,
+        decimal_mark: args.decimal_mark,
+        digit_grouping: args.digit_grouping,
+        keep_groups: args.keep_parens,
+    };
     let mut tex = String::new();
 
-    for e in expressions {
+    for statement in statements {
         tex.push_str(&format!(
             "\\begin{{displaymath}} {} \\end{{displaymath}}\n",
-            expression::evaulate(&e)
+            expression::evaulate(&statement.expr, &style)
         ))
     }
 
     let temp_dir = std::env::temp_dir();
-    let output_path = std::path::Path::new(&args.output_file);
+    let output_path = std::path::Path::new(&output_file);
     let file_name = output_path
         .file_stem()
         .expect("ERROR: Incorrect Output Path.");
@@ -60,15 +140,15 @@ fn run() -> Result<(), error::Error> {
     ];
 
     let mut temp_tex = std::fs::File::create(&temp_file_paths[0])?;
-    temp_tex.write(consts::PREFIX.as_bytes())?;
-    temp_tex.write(tex.as_bytes())?;
-    temp_tex.write(consts::SUFFIX.as_bytes())?;
+    temp_tex.write_all(consts::PREFIX.as_bytes())?;
+    temp_tex.write_all(tex.as_bytes())?;
+    temp_tex.write_all(consts::SUFFIX.as_bytes())?;
 
     let term_command = format!(
         "latex -output-directory={3} {0}  && dvipng -D 1000 -o {2} {1}",
         &temp_file_paths[0].display(),
         temp_file_paths[1].display(),
-        args.output_file.display(),
+        output_file.display(),
         temp_dir.display(),
     );
 
@@ -90,7 +170,7 @@ fn run() -> Result<(), error::Error> {
 
     if cfg!(target_os = "windows") && args.open {
         Command::new("cmd")
-            .args(["/C", &format!("start {}", args.output_file.display())])
+            .args(["/C", &format!("start {}", output_file.display())])
             .output()
             .expect("failed to execute process");
     };
@@ -0,0 +1,107 @@
+use std::fmt::Display;
+
+use crate::expression::{DecimalMark, DigitGrouping};
+
+/// A number literal, keeping the text it was written with so `1.50` stays
+/// `1.50` instead of going through a float.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Number {
+    pub text: String,
+    pub value: f64,
+}
+
+impl Number {
+    /// Reads a literal as lexed: a decimal with an optional exponent like
+    /// `6.02e23`, or a `0x` hexadecimal or `0b` binary integer, any of them
+    /// with `_` between digits.
+    pub fn new(text: String) -> Self {
+        let digits = text.replace('_', "");
+        let value = match radix_digits(&digits) {
+            Some((radix, digits)) => digits.chars().fold(0.0, |value, c| {
+                value * radix as f64 + c.to_digit(radix).unwrap_or_default() as f64
+            }),
+            None => digits.parse().unwrap_or(f64::NAN),
+        };
+        Self { text, value }
+    }
+
+    /// Whether the literal has an exponent, and so renders as a product
+    /// like `6.02 \times 10^{23}`.
+    pub fn is_scientific(&self) -> bool {
+        radix_digits(&self.text).is_none() && self.text.contains(['e', 'E'])
+    }
+
+    pub fn latex(&self, mark: DecimalMark, grouping: DigitGrouping) -> String {
+        let digits = self.text.replace('_', "");
+        if radix_digits(&digits).is_some() {
+            return format!("\\mathtt{{{}}}", digits);
+        }
+
+        let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+            None => (digits.as_str(), None),
+        };
+        let mut output = match mantissa.split_once('.') {
+            Some((integer, fraction)) => format!(
+                "{}{}{}",
+                group(integer, grouping, true),
+                mark.latex(),
+                group(fraction, grouping, false)
+            ),
+            None => group(mantissa, grouping, true),
+        };
+        if let Some(exponent) = exponent {
+            let exponent = exponent.strip_prefix('+').unwrap_or(exponent);
+            output.push_str(&format!(" \\times 10^{{{}}}", exponent));
+        }
+        output
+    }
+}
+
+impl From<usize> for Number {
+    fn from(value: usize) -> Self {
+        Self {
+            text: value.to_string(),
+            value: value as f64,
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Splits a `0x` or `0b` prefix off `digits`, returning the radix it names.
+fn radix_digits(digits: &str) -> Option<(u32, &str)> {
+    match digits.get(..2)? {
+        "0x" | "0X" => Some((16, &digits[2..])),
+        "0b" | "0B" => Some((2, &digits[2..])),
+        _ => None,
+    }
+}
+
+/// Separates `digits` into groups of three, counting from the decimal mark.
+/// Runs of four digits or fewer are left whole.
+fn group(digits: &str, grouping: DigitGrouping, integer: bool) -> String {
+    let separator = match grouping {
+        DigitGrouping::None => return digits.to_string(),
+        DigitGrouping::Thin => "\\,",
+        DigitGrouping::Comma => "{,}",
+    };
+    if digits.len() <= 4 {
+        return digits.to_string();
+    }
+
+    let first = match digits.len() % 3 {
+        n if integer && n != 0 => n,
+        _ => 3,
+    };
+    let mut output = digits[..first].to_string();
+    for chunk in digits.as_bytes()[first..].chunks(3) {
+        output.push_str(separator);
+        output.push_str(std::str::from_utf8(chunk).expect("digits are ascii"));
+    }
+    output
+}
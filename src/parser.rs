@@ -1,104 +1,472 @@
 use std::{iter::Peekable, vec};
 
 use crate::error::Error;
-use crate::expression::UnaryOp;
-use crate::lexer::TokenKind;
+use crate::expression::{
+    Annotation, BigOpKind, Bounds, DerivativeKind, Direction, IntegralKind, Quantifier, RelationOp,
+    UnaryOp, NOT_PRECEDENCE, RELATION_PRECEDENCE,
+};
+use crate::functions::{Arity, FunctionRegistry};
+use crate::lexer::{Comment, Lexer, TokenKind};
+use crate::number::Number;
+use crate::symbols;
 use crate::{
-    expression::{BinaryOp, Expr, Func},
+    expression::{BinaryOp, Expr},
     lexer::Token,
 };
 
-const MAX_PRECEDENCE: usize = 4;
+const MAX_PRECEDENCE: usize = 9;
+const POW_PRECEDENCE: usize = 8;
+const MULT_PRECEDENCE: usize = 7;
+
+/// Words that act as operators and so never start an implicitly multiplied
+/// operand, `x in A` is not `x * in * A`.
+const KEYWORDS: &[&str] = &[
+    "and",
+    "or",
+    "xor",
+    "not",
+    "union",
+    "intersection",
+    "setminus",
+    "choose",
+    "in",
+    "notin",
+    "subset",
+    "supset",
+    "prop",
+    "forall",
+    "exists",
+    "otherwise",
+    "where",
+    "for",
+];
+
+/// A single `;` terminated expression along with the lines it spans.
+#[derive(Debug)]
+pub struct Statement {
+    pub expr: Expr,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+    /// Comments written before the statement, or inside it.
+    pub comments: Vec<Comment>,
+    /// Comments following the statement on its last line.
+    pub trailing: Vec<Comment>,
+}
+
+/// Parses `source` and attaches its comments to the statements, the comments
+/// after the last statement are returned on their own.
+///
+/// Comments of the form `@function name/arity = template` or `@function name`
+/// declare functions on top of `functions` for this document.
+pub fn parse_document(
+    source: &str,
+    functions: &FunctionRegistry,
+) -> Result<(Vec<Statement>, Vec<Comment>), Error> {
+    let lexer = Lexer::new(&source);
+    let comments = lexer.clone().comments()?;
+
+    let mut functions = functions.clone();
+    for comment in &comments {
+        if comment.text.trim_start().starts_with("@function") {
+            functions
+                .declare_line(&comment.text)
+                .map_err(|message| Error::InvalidDeclaration {
+                    line: comment.line,
+                    message,
+                })?;
+        }
+    }
+
+    let mut statements = Parser::new(lexer.peekable(), functions).parse_statements()?;
+    let rest = attach_comments(&mut statements, comments);
+    Ok((statements, rest))
+}
+
+fn attach_comments(statements: &mut [Statement], comments: Vec<Comment>) -> Vec<Comment> {
+    let mut rest = vec![];
+    let mut index = 0;
+
+    for comment in comments {
+        let at = (comment.line, comment.col);
+        while index < statements.len()
+            && (statements[index].end_line, statements[index].end_col) < at
+        {
+            index += 1;
+        }
+
+        let inside = statements
+            .get(index)
+            .is_some_and(|statement| (statement.line, statement.col) < at);
+        let after_previous = index
+            .checked_sub(1)
+            .is_some_and(|previous| statements[previous].end_line == comment.line);
+
+        if !inside && after_previous {
+            statements[index - 1].trailing.push(comment);
+        } else if index < statements.len() {
+            statements[index].comments.push(comment);
+        } else {
+            rest.push(comment);
+        }
+    }
+
+    rest
+}
 
 pub struct Parser<I: Iterator<Item = Token>> {
     tokens: Peekable<I>,
+    functions: FunctionRegistry,
+}
+
+/// Whether a token can begin an operand multiplied with the one before it.
+fn starts_operand(kind: &TokenKind) -> bool {
+    match kind {
+        TokenKind::Number(_)
+        | TokenKind::Text(_)
+        | TokenKind::ParenOpen
+        | TokenKind::BracketOpen => true,
+        TokenKind::Identifier(name) => !KEYWORDS.contains(&name.as_str()),
+        _ => false,
+    }
 }
 
-impl<I: Iterator<Item = Token>> Parser<I> {
-    pub fn new(lexer: Peekable<I>) -> Self {
-        Self { tokens: lexer }
+/// Reads a two entry vector `[a, b]` where a set is expected as the closed
+/// interval it stands for.
+fn as_set(expr: Expr) -> Expr {
+    match expr {
+        Expr::Matrix(rows) if rows.len() == 2 && rows.iter().all(|row| row.len() == 1) => {
+            let mut entries = rows.into_iter().flatten();
+            let lower = entries.next().expect("checked to have two rows");
+            let upper = entries.next().expect("checked to have two rows");
+            Expr::Interval(Box::new(lower), Box::new(upper), true, true)
+        }
+        expr => expr,
     }
-    pub fn parse_all(&mut self) -> Result<Vec<Expr>, Error> {
+}
+
+/// Whether `name` can follow the `d` of a differential, as in `dx` or `dtheta`.
+pub fn is_differential_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_alphabetic(),
+        _ => symbols::lookup(name).is_some(),
+    }
+}
+
+impl<I: Iterator<Item = Token> + Clone> Parser<I> {
+    pub fn new(lexer: Peekable<I>, functions: FunctionRegistry) -> Self {
+        Self {
+            tokens: lexer,
+            functions,
+        }
+    }
+
+    pub fn parse_statements(&mut self) -> Result<Vec<Statement>, Error> {
         let mut output = vec![];
-        output.push(self.parse(0)?);
 
-        loop {
+        while let Some(first) = self.tokens.peek() {
+            let (line, col) = (first.line, first.col);
+            let expr = self.parse_annotated()?;
+
             match self.tokens.next() {
                 Some(Token {
                     kind: TokenKind::End,
+                    line: end_line,
+                    col: end_col,
                     ..
-                }) => {
-                    if let None = self.tokens.peek() {
-                        break;
-                    }
-                    output.push(self.parse(0)?)
-                }
+                }) => output.push(Statement {
+                    expr,
+                    line,
+                    col,
+                    end_line,
+                    end_col,
+                    comments: vec![],
+                    trailing: vec![],
+                }),
                 Some(token) => {
                     return Err(Error::UnexpectedToken {
                         expected: vec![TokenKind::End],
                         found: token,
                     })
                 }
-                None => break,
+                None => {
+                    output.push(Statement {
+                        expr,
+                        line,
+                        col,
+                        end_line: line,
+                        end_col: col,
+                        comments: vec![],
+                        trailing: vec![],
+                    });
+                    break;
+                }
             }
         }
 
         Ok(output)
     }
 
+    /// Parses a statement along with any `where` or `for` remarks after it.
+    fn parse_annotated(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse(0)?;
+        while let Some(annotation) = self.tokens.peek().and_then(|token| match &token.kind {
+            TokenKind::Identifier(name) => Annotation::from_name(name),
+            _ => None,
+        }) {
+            self.tokens.next();
+            let remark = self.parse(0)?;
+            expr = Expr::Annotated(Box::new(expr), annotation, Box::new(remark));
+        }
+        Ok(expr)
+    }
+
     fn parse(&mut self, precedence: usize) -> Result<Expr, Error> {
         if precedence >= MAX_PRECEDENCE {
             return self.parse_primary();
         }
-        let lhs = self.parse(precedence + 1)?;
+        if precedence == NOT_PRECEDENCE {
+            return self.parse_not();
+        }
+        if precedence == RELATION_PRECEDENCE {
+            return self.parse_relation();
+        }
+        let mut lhs = self.parse(precedence + 1)?;
 
-        if let Some(op) = self.tokens.peek() {
-            if op.kind.takes_precedence(precedence) {
-                match op.kind {
-                    TokenKind::Plus => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Add));
-                    }
-                    TokenKind::Minus => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Sub));
-                    }
-                    TokenKind::Multiply => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
+        while let Some(op) = self.tokens.peek() {
+            // Juxtaposition, `2x` or `(a + b)(a - b)`, multiplies.
+            if precedence == MULT_PRECEDENCE && starts_operand(&op.kind) {
+                let rhs = self.parse(precedence + 1)?;
+                lhs = Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Mult);
+                continue;
+            }
+            if !op.kind.takes_precedence(precedence) {
+                break;
+            }
+            if matches!(&op.kind, TokenKind::Identifier(name) if name == "choose") {
+                self.tokens.next();
+                let k = self.parse(precedence + 1)?;
+                lhs = Expr::Binomial(Box::new(lhs), vec![k]);
+                continue;
+            }
+            let op = match &op.kind {
+                TokenKind::Plus => BinaryOp::Add,
+                TokenKind::Minus => BinaryOp::Sub,
+                TokenKind::Multiply => BinaryOp::Mult,
+                TokenKind::Divider => BinaryOp::Frac,
+                TokenKind::Power => BinaryOp::Pow,
+                TokenKind::Iff => BinaryOp::Iff,
+                TokenKind::Implies => BinaryOp::Implies,
+                TokenKind::Identifier(name) if name == "and" => BinaryOp::And,
+                TokenKind::Identifier(name) if name == "or" => BinaryOp::Or,
+                TokenKind::Identifier(name) if name == "xor" => BinaryOp::Xor,
+                TokenKind::Identifier(name) if name == "union" => BinaryOp::Union,
+                TokenKind::Identifier(name) if name == "intersection" => BinaryOp::Intersection,
+                TokenKind::Identifier(name) if name == "setminus" => BinaryOp::SetMinus,
+                _ => break,
+            };
+            // A sign right before `)` or `,` marks a one-sided limit, `x -> 0+`
+            if matches!(op, BinaryOp::Add | BinaryOp::Sub)
+                && matches!(
+                    self.peek_nth(1),
+                    Some(TokenKind::ParenClose | TokenKind::Comma)
+                )
+            {
+                break;
+            }
+            self.tokens.next();
 
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Mult));
-                    }
-                    TokenKind::Divider => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
+            if matches!(op, BinaryOp::Pow) {
+                let rhs = self.parse_script(|parser| parser.parse(precedence))?;
+                return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), op));
+            }
+            if op.is_right_assoc() {
+                let rhs = self.parse(precedence)?;
+                return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), op));
+            }
+            let rhs = self.parse(precedence + 1)?;
+            if matches!(
+                op,
+                BinaryOp::Union | BinaryOp::Intersection | BinaryOp::SetMinus
+            ) {
+                lhs = Expr::Binary(Box::new(as_set(lhs)), Box::new(as_set(rhs)), op);
+            } else {
+                lhs = Expr::Binary(Box::new(lhs), Box::new(rhs), op);
+            }
+        }
 
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Frac));
-                    }
-                    TokenKind::Power => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
+        Ok(lhs)
+    }
 
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Pow));
-                    }
-                    TokenKind::Equals => {
-                        self.tokens.next();
-                        let rhs = self.parse(precedence)?;
-                        return Ok(Expr::Binary(Box::new(lhs), Box::new(rhs), BinaryOp::Equals));
-                    }
-                    _ => Ok(lhs),
+    fn parse_not(&mut self) -> Result<Expr, Error> {
+        if matches!(self.peek_nth(0), Some(TokenKind::Identifier(name)) if name == "not") {
+            self.tokens.next();
+            let expr = self.parse(NOT_PRECEDENCE)?;
+            return Ok(Expr::Unary(Box::new(expr), UnaryOp::Not));
+        }
+        self.parse(NOT_PRECEDENCE + 1)
+    }
+
+    /// Parses `forall x in R: body` once the quantifier was read, `exists`
+    /// may be followed by `!` for a unique existence. The `:` can be left out
+    /// between quantifiers, as in `forall e > 0 exists d > 0: body`.
+    fn parse_quantified(&mut self, mut quantifier: Quantifier) -> Result<Expr, Error> {
+        if matches!(quantifier, Quantifier::Exists) && self.peek_nth(0) == Some(TokenKind::Bang) {
+            self.tokens.next();
+            quantifier = Quantifier::ExistsUnique;
+        }
+        let binder = self.parse(RELATION_PRECEDENCE)?;
+        let nested = matches!(
+            self.peek_nth(0),
+            Some(TokenKind::Identifier(name)) if Quantifier::from_name(&name).is_some()
+        );
+        if !nested {
+            self.expect(TokenKind::Colon)?;
+        }
+        let body = self.parse(0)?;
+        Ok(Expr::Quantified(
+            quantifier,
+            Box::new(binder),
+            Box::new(body),
+        ))
+    }
+
+    /// Parses a chain of relations such as `0 < x <= 1` into a single node.
+    fn parse_relation(&mut self) -> Result<Expr, Error> {
+        let mut first = self.parse(RELATION_PRECEDENCE + 1)?;
+        let mut rest = vec![];
+
+        while let Some(op) = self.peek_relation() {
+            self.tokens.next();
+            let rhs = self.parse(RELATION_PRECEDENCE + 1)?;
+            if op.is_set_relation() {
+                match rest.last_mut() {
+                    Some((_, lhs)) => *lhs = as_set(std::mem::replace(lhs, Expr::Dots())),
+                    None => first = as_set(first),
                 }
+            }
+            if op.takes_set() {
+                rest.push((op, as_set(rhs)));
             } else {
-                Ok(lhs)
+                rest.push((op, rhs));
             }
+        }
+
+        if rest.is_empty() {
+            Ok(first)
         } else {
-            Ok(lhs)
+            Ok(Expr::Relation(Box::new(first), rest))
         }
     }
 
+    fn peek_relation(&mut self) -> Option<RelationOp> {
+        match &self.tokens.peek()?.kind {
+            TokenKind::Equals => Some(RelationOp::Eq),
+            TokenKind::NotEquals => Some(RelationOp::Ne),
+            TokenKind::Less => Some(RelationOp::Lt),
+            TokenKind::LessEquals => Some(RelationOp::Le),
+            TokenKind::Greater => Some(RelationOp::Gt),
+            TokenKind::GreaterEquals => Some(RelationOp::Ge),
+            TokenKind::Approx => Some(RelationOp::Approx),
+            TokenKind::Tilde => Some(RelationOp::Sim),
+            TokenKind::Equiv => Some(RelationOp::Equiv),
+            TokenKind::Propto => Some(RelationOp::Propto),
+            TokenKind::Identifier(name) => match name.as_str() {
+                "prop" => Some(RelationOp::Propto),
+                "in" => Some(RelationOp::In),
+                "notin" => Some(RelationOp::NotIn),
+                "subset" => Some(RelationOp::Subset),
+                "supset" => Some(RelationOp::Supset),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses a primary along with any `_` subscripts and `'` primes
+    /// following it, so that `x_i^2` is `(x_i)^2` and `f'(x)` a derivative.
     fn parse_primary(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_atom()?;
+
+        loop {
+            match self.peek_nth(0) {
+                Some(TokenKind::Underscore) => {
+                    self.tokens.next();
+                    let index = match self.parse_subscript_name() {
+                        Some(name) => Expr::Sym(name),
+                        None => self.parse_script(Self::parse_atom)?,
+                    };
+                    expr = Expr::Sub(Box::new(expr), Box::new(index));
+                }
+                Some(TokenKind::Prime) => {
+                    let mut order = 0;
+                    while let Some(TokenKind::Prime) = self.peek_nth(0) {
+                        self.tokens.next();
+                        order += 1;
+                    }
+                    let args = match self.peek_nth(0) {
+                        Some(TokenKind::ParenOpen) => {
+                            self.tokens.next();
+                            self.parse_args()?
+                        }
+                        _ => vec![],
+                    };
+                    expr = Expr::Derivative(
+                        Box::new(expr),
+                        vec![],
+                        DerivativeKind::Prime(order, args),
+                    );
+                }
+                Some(TokenKind::Bang) => {
+                    self.tokens.next();
+                    let mut order = 1;
+                    if self.peek_nth(0) == Some(TokenKind::Bang) {
+                        self.tokens.next();
+                        order = 2;
+                    }
+                    expr = Expr::Factorial(Box::new(expr), order);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a subscript or exponent, either braced as in `x_{i+1}` and
+    /// `x^{n+1}` or the plain operand `parse` reads.
+    fn parse_script(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Expr, Error>,
+    ) -> Result<Expr, Error> {
+        if self.peek_nth(0) != Some(TokenKind::BraceOpen) {
+            return parse(self);
+        }
+        self.tokens.next();
+        let script = self.parse(0)?;
+        self.expect(TokenKind::BraceClose)?;
+        Ok(script)
+    }
+
+    /// The kind of the token `n` places ahead, without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<TokenKind> {
+        self.tokens.clone().nth(n).map(|token| token.kind)
+    }
+
+    fn expect(&mut self, expected: TokenKind) -> Result<Token, Error> {
+        match self.tokens.next() {
+            Some(token) if token.kind == expected => Ok(token),
+            Some(token) => Err(Error::UnexpectedToken {
+                expected: vec![expected],
+                found: token,
+            }),
+            None => Err(Error::UnexpectedEOF),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr, Error> {
         if let Some(primary) = self.tokens.next() {
             match primary.kind {
                 TokenKind::Dot => {
@@ -131,15 +499,42 @@ impl<I: Iterator<Item = Token>> Parser<I> {
                         (None, _) => Err(Error::UnexpectedEOF),
                     }
                 }
-                TokenKind::Minus => Ok(Expr::Unary(Box::new(self.parse(0)?), UnaryOp::Sub)),
+                TokenKind::Minus => Ok(Expr::Unary(
+                    Box::new(self.parse(POW_PRECEDENCE)?),
+                    UnaryOp::Sub,
+                )),
                 TokenKind::Number(a) => Ok(Expr::Val(a)),
-                TokenKind::Identifier(a) if a.len() == 1 => Ok(Expr::Sym(a.as_bytes()[0] as char)),
+                TokenKind::Text(a) => Ok(Expr::Text(a)),
                 TokenKind::ParenOpen => {
                     let expr = self.parse(0)?;
                     let next = self.tokens.next();
                     if let Some(token) = next {
                         match token.kind {
-                            TokenKind::ParenClose => Ok(expr),
+                            TokenKind::ParenClose => Ok(Expr::Group(Box::new(expr))),
+                            // `(a, b)` and `(a, b]` are intervals
+                            TokenKind::Comma => {
+                                let upper = self.parse(0)?;
+                                match self.tokens.next() {
+                                    Some(Token {
+                                        kind:
+                                            kind @ (TokenKind::ParenClose | TokenKind::BracketClose),
+                                        ..
+                                    }) => Ok(Expr::Interval(
+                                        Box::new(expr),
+                                        Box::new(upper),
+                                        false,
+                                        kind == TokenKind::BracketClose,
+                                    )),
+                                    Some(token) => Err(Error::UnexpectedToken {
+                                        expected: vec![
+                                            TokenKind::ParenClose,
+                                            TokenKind::BracketClose,
+                                        ],
+                                        found: token,
+                                    }),
+                                    None => Err(Error::UnexpectedEOF),
+                                }
+                            }
                             _ => Err(Error::UnexpectedToken {
                                 expected: vec![TokenKind::ParenClose],
                                 found: token,
@@ -149,41 +544,61 @@ impl<I: Iterator<Item = Token>> Parser<I> {
                         Err(Error::UnexpectedEOF)
                     }
                 }
+                TokenKind::BraceOpen => self.parse_set(),
+                TokenKind::Pipe => self.parse_bars(),
+                TokenKind::BracketOpen => self.parse_matrix(primary),
                 TokenKind::ParenClose => Err(Error::UnexpectedToken {
                     expected: vec![
                         TokenKind::Dot,
                         TokenKind::Minus,
-                        TokenKind::Number(0.0),
+                        TokenKind::Number(Number::from(0)),
                         TokenKind::Identifier("".to_string()),
                         TokenKind::ParenOpen,
                     ],
                     found: primary,
                 }),
+                // Known function names are always calls, as are names declared
+                // to be applied like `f(x)`. Any other identifier is a variable
+                // and is multiplied with parentheses following it.
                 TokenKind::Identifier(a) => {
-                    let next = self.tokens.next();
-                    if let Some(token) = next {
-                        match token.kind {
-                            TokenKind::ParenOpen => {
-                                let args = self.parse_args()?;
-                                let a: Func = a.try_into()?;
-                                if a.nr_of_args() != args.len() {
-                                    Err(Error::WrongArguments {
-                                        line: token.line,
-                                        col: token.col,
-                                        found: args.len(),
-                                        expected: a.nr_of_args(),
-                                    })
-                                } else {
-                                    Ok(Expr::Func(a, args))
-                                }
-                            }
-                            _ => Err(Error::UnexpectedToken {
-                                expected: vec![TokenKind::ParenOpen],
-                                found: token,
-                            }),
+                    let is_call = matches!(
+                        self.tokens.peek(),
+                        Some(Token {
+                            kind: TokenKind::ParenOpen,
+                            ..
+                        })
+                    );
+                    if let Some(derivative) = self.parse_leibniz(&a)? {
+                        Ok(derivative)
+                    } else if let Some(quantifier) = Quantifier::from_name(&a) {
+                        self.parse_quantified(quantifier)
+                    } else if is_call && (a == "diff" || a == "partial") {
+                        self.parse_diff(a == "partial")
+                    } else if let Some(kind) = IntegralKind::from_name(&a).filter(|_| is_call) {
+                        self.parse_integral(kind)
+                    } else if let Some(kind) = BigOpKind::from_name(&a).filter(|_| is_call) {
+                        self.parse_big_op(kind)
+                    } else if is_call && a == "lim" {
+                        self.parse_limit()
+                    } else if is_call && a == "cases" {
+                        self.parse_cases()
+                    } else if is_call && a == "binom" {
+                        self.parse_binomial()
+                    } else if is_call && (a == "abs" || a == "norm") {
+                        self.expect(TokenKind::ParenOpen)?;
+                        let expr = Box::new(self.parse(0)?);
+                        self.expect(TokenKind::ParenClose)?;
+                        match a.as_str() {
+                            "abs" => Ok(Expr::Abs(expr)),
+                            _ => Ok(Expr::Norm(expr)),
                         }
+                    } else if self.functions.contains(&a) {
+                        self.parse_call_or_power(a)
+                    } else if is_call && self.functions.is_applied(&a) {
+                        self.tokens.next();
+                        Ok(Expr::Apply(a, self.parse_args()?))
                     } else {
-                        Err(Error::UnexpectedEOF)
+                        Ok(Expr::Sym(a))
                     }
                 }
                 _ => Err(Error::UnexpectedToken {
@@ -196,6 +611,537 @@ impl<I: Iterator<Item = Token>> Parser<I> {
         }
     }
 
+    /// Matches the Leibniz forms `d/dx(f)`, `dy/dx`, `d^2/dx^2(f)` and
+    /// `d^2y/dx^2` once `first` was read, leaving the tokens alone and
+    /// returning `None` when they are not one of those.
+    fn parse_leibniz(&mut self, first: &str) -> Result<Option<Expr>, Error> {
+        let Some(mut function) = first.strip_prefix('d').map(str::to_string) else {
+            return Ok(None);
+        };
+        if !function.is_empty() && !is_differential_name(&function) {
+            return Ok(None);
+        }
+
+        let mut i = 0;
+        let mut order = 1;
+        if function.is_empty() {
+            if let (Some(TokenKind::Power), Some(TokenKind::Number(n))) =
+                (self.peek_nth(0), self.peek_nth(1))
+            {
+                if n.value.fract() != 0.0 || n.value < 1.0 {
+                    return Ok(None);
+                }
+                order = n.value as usize;
+                i = 2;
+                if let Some(TokenKind::Identifier(name)) = self.peek_nth(i) {
+                    if is_differential_name(&name) {
+                        function = name;
+                        i += 1;
+                    }
+                }
+            }
+        }
+
+        if self.peek_nth(i) != Some(TokenKind::Divider) {
+            return Ok(None);
+        }
+        let var = match self.peek_nth(i + 1) {
+            Some(TokenKind::Identifier(name)) => match name.strip_prefix('d') {
+                Some(var) if is_differential_name(var) => var.to_string(),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        i += 2;
+
+        if order > 1 {
+            if (self.peek_nth(i), self.peek_nth(i + 1))
+                != (
+                    Some(TokenKind::Power),
+                    Some(TokenKind::Number(Number::from(order))),
+                )
+            {
+                return Ok(None);
+            }
+            i += 2;
+        }
+        if function.is_empty() && self.peek_nth(i) != Some(TokenKind::ParenOpen) {
+            return Ok(None);
+        }
+
+        for _ in 0..i {
+            self.tokens.next();
+        }
+        let expr = if function.is_empty() {
+            self.expect(TokenKind::ParenOpen)?;
+            let expr = self.parse(0)?;
+            self.expect(TokenKind::ParenClose)?;
+            expr
+        } else {
+            Expr::Sym(function)
+        };
+
+        Ok(Some(Expr::Derivative(
+            Box::new(expr),
+            vec![(Expr::Sym(var), order)],
+            DerivativeKind::Leibniz,
+        )))
+    }
+
+    /// Parses `diff(f, x)`, `diff(f, x, n)` and `partial(f, x, y, ...)`, a
+    /// variable repeated in a row is one higher order derivative.
+    fn parse_diff(&mut self, partial: bool) -> Result<Expr, Error> {
+        let token = self.expect(TokenKind::ParenOpen)?;
+        let mut args = self.parse_args()?;
+        let arity = match partial {
+            true => Arity { min: 2, max: None },
+            false => Arity {
+                min: 2,
+                max: Some(3),
+            },
+        };
+        if !arity.accepts(args.len()) {
+            return Err(Error::WrongArguments {
+                line: token.line,
+                col: token.col,
+                found: args.len(),
+                expected: arity,
+            });
+        }
+
+        let expr = args.remove(0);
+        let mut vars: Vec<(Expr, usize)> = vec![];
+        if partial {
+            for var in args {
+                match vars.last_mut() {
+                    Some((Expr::Sym(last), order)) if matches!(&var, Expr::Sym(name) if name == last) =>
+                    {
+                        *order += 1;
+                    }
+                    _ => vars.push((var, 1)),
+                }
+            }
+        } else {
+            let order = match args.get(1).map(Expr::ungrouped) {
+                None => 1,
+                Some(Expr::Val(n)) if n.value.fract() == 0.0 && n.value >= 1.0 => n.value as usize,
+                Some(_) => {
+                    return Err(Error::InvalidOrder {
+                        line: token.line,
+                        col: token.col,
+                    })
+                }
+            };
+            vars.push((args.remove(0), order));
+        }
+
+        let kind = match partial {
+            true => DerivativeKind::Partial,
+            false => DerivativeKind::Leibniz,
+        };
+        Ok(Expr::Derivative(Box::new(expr), vars, kind))
+    }
+
+    /// Parses `int(f, x)`, `int(f, x, a, b)` and `int(f, x, D)` along with
+    /// their `iint`, `iiint` and `oint` versions, which take one variable, and
+    /// its limits, per integral sign.
+    fn parse_integral(&mut self, kind: IntegralKind) -> Result<Expr, Error> {
+        let token = self.expect(TokenKind::ParenOpen)?;
+        let mut args = self.parse_args()?.into_iter();
+        let n = kind.nr_of_vars();
+        let found = args.len();
+
+        let expr = Box::new(args.next().expect("parse_args returns at least one"));
+        let (vars, bounds) = if found == 1 + n {
+            (args.collect(), Bounds::None)
+        } else if found == 2 + n {
+            let mut vars: Vec<Expr> = args.collect();
+            let region = vars.pop().expect("found is at least 3");
+            (vars, Bounds::Region(Box::new(region)))
+        } else if found == 1 + 3 * n {
+            let mut vars = vec![];
+            let mut limits = vec![];
+            while let (Some(var), Some(lower), Some(upper)) =
+                (args.next(), args.next(), args.next())
+            {
+                vars.push(var);
+                limits.push((lower, upper));
+            }
+            (vars, Bounds::Each(limits))
+        } else {
+            return Err(Error::WrongArgumentsOneOf {
+                line: token.line,
+                col: token.col,
+                found,
+                expected: vec![1 + n, 2 + n, 1 + 3 * n],
+            });
+        };
+
+        Ok(Expr::Integral(expr, vars, bounds, kind))
+    }
+
+    /// Parses `sum(body, k)`, `sum(body, k, S)` and `sum(body, k, a, b)`,
+    /// and likewise for `prod`, `union` and `intersection`.
+    fn parse_big_op(&mut self, kind: BigOpKind) -> Result<Expr, Error> {
+        let token = self.expect(TokenKind::ParenOpen)?;
+        let mut args = self.parse_args()?.into_iter();
+        let found = args.len();
+        if !(2..=4).contains(&found) {
+            return Err(Error::WrongArguments {
+                line: token.line,
+                col: token.col,
+                found,
+                expected: Arity {
+                    min: 2,
+                    max: Some(4),
+                },
+            });
+        }
+
+        let body = Box::new(args.next().expect("found is at least 2"));
+        let index = Box::new(args.next().expect("found is at least 2"));
+        let bounds = match (args.next(), args.next()) {
+            (None, _) => Bounds::None,
+            (Some(set), None) => Bounds::Region(Box::new(set)),
+            (Some(lower), Some(upper)) => Bounds::Each(vec![(lower, upper)]),
+        };
+
+        Ok(Expr::BigOp(body, index, bounds, kind))
+    }
+
+    /// Parses a matrix `[[a, b], [c, d]]` once the first `[` was read, or a
+    /// column vector `[a, b]`, checking that every row is as long as the first.
+    fn parse_matrix(&mut self, open: Token) -> Result<Expr, Error> {
+        let rows = if self.peek_nth(0) == Some(TokenKind::BracketOpen) {
+            let mut rows = vec![];
+            loop {
+                self.expect(TokenKind::BracketOpen)?;
+                rows.push(self.parse_entries(TokenKind::BracketClose)?.0);
+                match self.tokens.next() {
+                    Some(Token {
+                        kind: TokenKind::Comma,
+                        ..
+                    }) => {}
+                    Some(Token {
+                        kind: TokenKind::BracketClose,
+                        ..
+                    }) => break,
+                    Some(token) => {
+                        return Err(Error::UnexpectedToken {
+                            expected: vec![TokenKind::Comma, TokenKind::BracketClose],
+                            found: token,
+                        })
+                    }
+                    None => return Err(Error::UnexpectedEOF),
+                }
+            }
+            rows
+        } else {
+            let (entries, close) = self.parse_entries(TokenKind::ParenClose)?;
+            if close.kind == TokenKind::ParenClose {
+                let [lower, upper] =
+                    <[Expr; 2]>::try_from(entries).map_err(|_| Error::UnexpectedToken {
+                        expected: vec![TokenKind::BracketClose],
+                        found: close,
+                    })?;
+                return Ok(Expr::Interval(
+                    Box::new(lower),
+                    Box::new(upper),
+                    true,
+                    false,
+                ));
+            }
+            entries.into_iter().map(|entry| vec![entry]).collect()
+        };
+
+        let expected = rows[0].len();
+        if let Some((row, entries)) = rows
+            .iter()
+            .enumerate()
+            .find(|(_, entries)| entries.len() != expected)
+        {
+            return Err(Error::MatrixShape {
+                line: open.line,
+                col: open.col,
+                row: row + 1,
+                found: entries.len(),
+                expected,
+            });
+        }
+
+        Ok(Expr::Matrix(rows))
+    }
+
+    /// Parses comma separated entries up to and including the closing `]`, or
+    /// `other` which is returned so the caller can tell which one it was.
+    fn parse_entries(&mut self, other: TokenKind) -> Result<(Vec<Expr>, Token), Error> {
+        let mut entries = vec![];
+        loop {
+            entries.push(self.parse(0)?);
+            match self.tokens.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => {}
+                Some(token) if token.kind == TokenKind::BracketClose || token.kind == other => {
+                    return Ok((entries, token))
+                }
+                Some(token) => {
+                    return Err(Error::UnexpectedToken {
+                        expected: vec![TokenKind::Comma, TokenKind::BracketClose],
+                        found: token,
+                    })
+                }
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+    }
+
+    /// Parses a set `{1, 2, 3}`, the empty set `{}` or the set-builder
+    /// `{x | x > 0}` once the `{` was read.
+    fn parse_set(&mut self) -> Result<Expr, Error> {
+        if self.peek_nth(0) == Some(TokenKind::BraceClose) {
+            self.tokens.next();
+            return Ok(Expr::Set(vec![]));
+        }
+
+        let first = self.parse(0)?;
+        if self.peek_nth(0) == Some(TokenKind::Pipe) {
+            self.tokens.next();
+            let condition = self.parse(0)?;
+            self.expect(TokenKind::BraceClose)?;
+            return Ok(Expr::SetBuilder(Box::new(first), Box::new(condition)));
+        }
+
+        let mut elements = vec![first];
+        loop {
+            match self.tokens.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => elements.push(self.parse(0)?),
+                Some(Token {
+                    kind: TokenKind::BraceClose,
+                    ..
+                }) => return Ok(Expr::Set(elements)),
+                Some(token) => {
+                    return Err(Error::UnexpectedToken {
+                        expected: vec![TokenKind::Comma, TokenKind::BraceClose],
+                        found: token,
+                    })
+                }
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+    }
+
+    /// Parses `|x|` or `||v||` once the first `|` was read. A `|` where an
+    /// operator would go closes the innermost bars, so `|a - |b||` nests.
+    /// `||` is read as a norm unless that fails, as in `||x| - 1|`, in which
+    /// case it is read again as two absolute values.
+    fn parse_bars(&mut self) -> Result<Expr, Error> {
+        if self.peek_nth(0) == Some(TokenKind::Pipe) {
+            let saved = self.tokens.clone();
+            self.tokens.next();
+            let norm = self.parse(0).and_then(|expr| {
+                self.expect(TokenKind::Pipe)?;
+                self.expect(TokenKind::Pipe)?;
+                Ok(expr)
+            });
+            match norm {
+                Ok(expr) => return Ok(Expr::Norm(Box::new(expr))),
+                Err(_) => self.tokens = saved,
+            }
+        }
+
+        let expr = self.parse(0)?;
+        self.expect(TokenKind::Pipe)?;
+        Ok(Expr::Abs(Box::new(expr)))
+    }
+
+    /// Parses `binom(n, k)` and the multinomial `binom(n, k1, k2, ...)`.
+    fn parse_binomial(&mut self) -> Result<Expr, Error> {
+        let token = self.expect(TokenKind::ParenOpen)?;
+        let mut args = self.parse_args()?;
+        if args.len() < 2 {
+            return Err(Error::WrongArguments {
+                line: token.line,
+                col: token.col,
+                found: args.len(),
+                expected: Arity { min: 2, max: None },
+            });
+        }
+        let n = args.remove(0);
+        Ok(Expr::Binomial(Box::new(n), args))
+    }
+
+    /// Parses `cases(x, x >= 0; -x, otherwise)`, arms of a value and its
+    /// condition separated by `;`, which may also end the last arm.
+    fn parse_cases(&mut self) -> Result<Expr, Error> {
+        self.expect(TokenKind::ParenOpen)?;
+        let mut arms = vec![];
+
+        loop {
+            let value = self.parse(0)?;
+            self.expect(TokenKind::Comma)?;
+            let condition = match self.peek_nth(0) {
+                Some(TokenKind::Identifier(name))
+                    if name == "otherwise"
+                        && matches!(
+                            self.peek_nth(1),
+                            Some(TokenKind::End | TokenKind::ParenClose)
+                        ) =>
+                {
+                    self.tokens.next();
+                    None
+                }
+                _ => Some(self.parse(0)?),
+            };
+            arms.push((value, condition));
+
+            match self.tokens.next() {
+                Some(Token {
+                    kind: TokenKind::End,
+                    ..
+                }) if self.peek_nth(0) == Some(TokenKind::ParenClose) => {
+                    self.tokens.next();
+                    break;
+                }
+                Some(Token {
+                    kind: TokenKind::End,
+                    ..
+                }) => {}
+                Some(Token {
+                    kind: TokenKind::ParenClose,
+                    ..
+                }) => break,
+                Some(token) => {
+                    return Err(Error::UnexpectedToken {
+                        expected: vec![TokenKind::End, TokenKind::ParenClose],
+                        found: token,
+                    })
+                }
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+
+        Ok(Expr::Piecewise(arms))
+    }
+
+    /// Parses `lim(f, x, a)` and `lim(f, x -> a)`, where the target may be
+    /// followed by `+` or `-` for a limit from above or below.
+    fn parse_limit(&mut self) -> Result<Expr, Error> {
+        self.expect(TokenKind::ParenOpen)?;
+        let expr = self.parse(0)?;
+        self.expect(TokenKind::Comma)?;
+        let var = self.parse(0)?;
+        match self.tokens.next() {
+            Some(Token {
+                kind: TokenKind::Comma | TokenKind::Arrow,
+                ..
+            }) => {}
+            Some(token) => {
+                return Err(Error::UnexpectedToken {
+                    expected: vec![TokenKind::Comma, TokenKind::Arrow],
+                    found: token,
+                })
+            }
+            None => return Err(Error::UnexpectedEOF),
+        }
+        let target = self.parse(0)?;
+        let direction = match self.peek_nth(0) {
+            Some(TokenKind::Plus) => Direction::Above,
+            Some(TokenKind::Minus) => Direction::Below,
+            _ => Direction::Both,
+        };
+        if direction != Direction::Both {
+            self.tokens.next();
+        }
+        self.expect(TokenKind::ParenClose)?;
+
+        Ok(Expr::Limit(
+            Box::new(expr),
+            Box::new(var),
+            Box::new(target),
+            direction,
+        ))
+    }
+
+    /// Reads a function name written as a subscript, like the `max` in
+    /// `T_max` or `T_{max}`, where it labels the variable instead of calling
+    /// anything. Leaves the tokens alone and returns `None` otherwise.
+    fn parse_subscript_name(&mut self) -> Option<String> {
+        let braced = self.peek_nth(0) == Some(TokenKind::BraceOpen);
+        let Some(TokenKind::Identifier(name)) = self.peek_nth(usize::from(braced)) else {
+            return None;
+        };
+        if !self.functions.contains(&name) {
+            return None;
+        }
+
+        let saved = self.tokens.clone();
+        self.tokens.nth(usize::from(braced));
+        if braced && self.peek_nth(0) == Some(TokenKind::BraceClose) {
+            self.tokens.next();
+            return Some(name);
+        }
+        if !braced && !self.peek_call() {
+            return Some(name);
+        }
+        self.tokens = saved;
+        None
+    }
+
+    /// Whether the tokens after a function name call it, with `(` right away
+    /// or after a power as in `sin^2(x)`.
+    fn peek_call(&mut self) -> bool {
+        match self.peek_nth(0) {
+            Some(TokenKind::ParenOpen) => true,
+            Some(TokenKind::Power) => {
+                let saved = self.tokens.clone();
+                self.tokens.next();
+                let is_call = self.parse_script(Self::parse_primary).is_ok()
+                    && self.peek_nth(0) == Some(TokenKind::ParenOpen);
+                self.tokens = saved;
+                is_call
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a call, also accepting the power written in front of the
+    /// arguments as in `sin^2(x)`, which is read as `sin(x)^2`.
+    fn parse_call_or_power(&mut self, name: String) -> Result<Expr, Error> {
+        if let Some(Token {
+            kind: TokenKind::Power,
+            ..
+        }) = self.tokens.peek()
+        {
+            self.tokens.next();
+            let power = self.parse_script(Self::parse_primary)?;
+            let call = self.parse_call(name)?;
+            return Ok(Expr::Binary(Box::new(call), Box::new(power), BinaryOp::Pow));
+        }
+        self.parse_call(name)
+    }
+
+    fn parse_call(&mut self, name: String) -> Result<Expr, Error> {
+        let token = self.expect(TokenKind::ParenOpen)?;
+        let args = self.parse_args()?;
+        match self.functions.get(&name, args.len()) {
+            Some(function) => Ok(Expr::Func(function, args)),
+            None => Err(Error::WrongArguments {
+                line: token.line,
+                col: token.col,
+                found: args.len(),
+                expected: self
+                    .functions
+                    .arity(&name)
+                    .expect("only called for declared functions"),
+            }),
+        }
+    }
+
     fn parse_args(&mut self) -> Result<Vec<Expr>, Error> {
         let mut output = vec![];
         output.push(self.parse(0)?);
@@ -215,14 +1161,101 @@ impl<I: Iterator<Item = Token>> Parser<I> {
             ..
         }) = next
         {
-            return Ok(output);
+            Ok(output)
         } else if let Some(token) = next {
-            return Err(Error::UnexpectedToken {
+            Err(Error::UnexpectedToken {
                 expected: vec![TokenKind::ParenClose],
                 found: token,
-            });
+            })
         } else {
-            return Err(Error::UnexpectedEOF);
+            Err(Error::UnexpectedEOF)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The expression of the single statement in `source`.
+    fn parse(source: &str) -> Expr {
+        let (mut statements, _) = parse_document(source, &FunctionRegistry::default())
+            .unwrap_or_else(|error| panic!("{} in {}", error, source));
+        assert_eq!(statements.len(), 1);
+        statements.remove(0).expr
+    }
+
+    fn fails(source: &str) -> bool {
+        parse_document(source, &FunctionRegistry::default()).is_err()
+    }
+
+    #[test]
+    fn bars() {
+        assert!(matches!(parse("||v||"), Expr::Norm(_)));
+        // `||` that cannot close as a norm is read again as two bars.
+        match parse("||x| - 1|") {
+            Expr::Abs(a) => assert!(matches!(
+                *a,
+                Expr::Binary(ref x, _, BinaryOp::Sub) if matches!(**x, Expr::Abs(_))
+            )),
+            expr => panic!("expected an absolute value, found {:?}", expr),
+        }
+        match parse("|a - |b||") {
+            Expr::Abs(a) => assert!(matches!(
+                *a,
+                Expr::Binary(_, ref b, BinaryOp::Sub) if matches!(**b, Expr::Abs(_))
+            )),
+            expr => panic!("expected an absolute value, found {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn intervals_and_vectors() {
+        assert!(matches!(parse("[a, b)"), Expr::Interval(_, _, true, false)));
+        assert!(matches!(parse("(a, b]"), Expr::Interval(_, _, false, true)));
+        assert!(matches!(parse("[a, b]"), Expr::Matrix(rows) if rows.len() == 2));
+        assert!(matches!(
+            parse("x in [a, b]"),
+            Expr::Relation(_, rest) if matches!(rest[0].1, Expr::Interval(_, _, true, true))
+        ));
+    }
+
+    #[test]
+    fn leibniz() {
+        assert!(matches!(
+            parse("d/dx(x^2)"),
+            Expr::Derivative(_, vars, DerivativeKind::Leibniz) if vars.len() == 1
+        ));
+        assert!(matches!(
+            parse("dy/dx"),
+            Expr::Derivative(_, _, DerivativeKind::Leibniz)
+        ));
+        assert!(matches!(
+            parse("d^2y/dx^2"),
+            Expr::Derivative(_, vars, _) if vars[0].1 == 2
+        ));
+        // Without a differential after the `/` it is a plain division.
+        assert!(matches!(parse("d/2"), Expr::Binary(_, _, BinaryOp::Frac)));
+        assert!(matches!(parse("dt/2"), Expr::Binary(_, _, BinaryOp::Frac)));
+    }
+
+    #[test]
+    fn function_names() {
+        assert!(fails("sin x;"));
+        assert!(matches!(parse("T_max"), Expr::Sub(_, b) if matches!(*b, Expr::Sym(_))));
+        assert!(matches!(parse("T_{min}"), Expr::Sub(_, b) if matches!(*b, Expr::Sym(_))));
+        assert!(matches!(parse("x_max(a, b)"), Expr::Sub(_, b) if matches!(*b, Expr::Func(_, _))));
+    }
+
+    #[test]
+    fn braced_exponents() {
+        assert!(matches!(
+            parse("x^{n + 1}"),
+            Expr::Binary(_, b, BinaryOp::Pow) if matches!(*b, Expr::Binary(_, _, BinaryOp::Add))
+        ));
+        assert!(matches!(
+            parse("x^{2}"),
+            Expr::Binary(_, b, BinaryOp::Pow) if matches!(*b, Expr::Val(_))
+        ));
+    }
+}
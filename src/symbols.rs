@@ -0,0 +1,84 @@
+/// A letter or constant that can be written either by name or by its Unicode
+/// character, e.g. `theta` and `θ` both render as `\theta`.
+pub struct Symbol {
+    pub name: &'static str,
+    pub unicode: char,
+    pub latex: &'static str,
+}
+
+const fn symbol(name: &'static str, unicode: char, latex: &'static str) -> Symbol {
+    Symbol {
+        name,
+        unicode,
+        latex,
+    }
+}
+
+pub const SYMBOLS: &[Symbol] = &[
+    symbol("alpha", 'α', "\\alpha"),
+    symbol("beta", 'β', "\\beta"),
+    symbol("gamma", 'γ', "\\gamma"),
+    symbol("delta", 'δ', "\\delta"),
+    symbol("epsilon", 'ϵ', "\\epsilon"),
+    symbol("varepsilon", 'ε', "\\varepsilon"),
+    symbol("zeta", 'ζ', "\\zeta"),
+    symbol("eta", 'η', "\\eta"),
+    symbol("theta", 'θ', "\\theta"),
+    symbol("vartheta", 'ϑ', "\\vartheta"),
+    symbol("iota", 'ι', "\\iota"),
+    symbol("kappa", 'κ', "\\kappa"),
+    symbol("lambda", 'λ', "\\lambda"),
+    symbol("mu", 'μ', "\\mu"),
+    symbol("nu", 'ν', "\\nu"),
+    symbol("xi", 'ξ', "\\xi"),
+    symbol("omicron", 'ο', "o"),
+    symbol("pi", 'π', "\\pi"),
+    symbol("varpi", 'ϖ', "\\varpi"),
+    symbol("rho", 'ρ', "\\rho"),
+    symbol("varrho", 'ϱ', "\\varrho"),
+    symbol("sigma", 'σ', "\\sigma"),
+    symbol("varsigma", 'ς', "\\varsigma"),
+    symbol("tau", 'τ', "\\tau"),
+    symbol("upsilon", 'υ', "\\upsilon"),
+    symbol("phi", 'ϕ', "\\phi"),
+    symbol("varphi", 'φ', "\\varphi"),
+    symbol("chi", 'χ', "\\chi"),
+    symbol("psi", 'ψ', "\\psi"),
+    symbol("omega", 'ω', "\\omega"),
+    symbol("Gamma", 'Γ', "\\Gamma"),
+    symbol("Delta", 'Δ', "\\Delta"),
+    symbol("Theta", 'Θ', "\\Theta"),
+    symbol("Lambda", 'Λ', "\\Lambda"),
+    symbol("Xi", 'Ξ', "\\Xi"),
+    symbol("Pi", 'Π', "\\Pi"),
+    symbol("Sigma", 'Σ', "\\Sigma"),
+    symbol("Upsilon", 'Υ', "\\Upsilon"),
+    symbol("Phi", 'Φ', "\\Phi"),
+    symbol("Psi", 'Ψ', "\\Psi"),
+    symbol("Omega", 'Ω', "\\Omega"),
+    symbol("infinity", '∞', "\\infty"),
+    symbol("inf", '∞', "\\infty"),
+    symbol("hbar", 'ℏ', "\\hbar"),
+    symbol("ell", 'ℓ', "\\ell"),
+    symbol("nabla", '∇', "\\nabla"),
+    symbol("emptyset", '∅', "\\emptyset"),
+    symbol("NN", 'ℕ', "\\mathbb{N}"),
+    symbol("ZZ", 'ℤ', "\\mathbb{Z}"),
+    symbol("QQ", 'ℚ', "\\mathbb{Q}"),
+    symbol("RR", 'ℝ', "\\mathbb{R}"),
+    symbol("CC", 'ℂ', "\\mathbb{C}"),
+];
+
+/// Finds a symbol by its name, or by its character when `name` is a single
+/// Unicode character from the table.
+pub fn lookup(name: &str) -> Option<&'static Symbol> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_ascii() => by_char(c),
+        _ => SYMBOLS.iter().find(|symbol| symbol.name == name),
+    }
+}
+
+pub fn by_char(c: char) -> Option<&'static Symbol> {
+    SYMBOLS.iter().find(|symbol| symbol.unicode == c)
+}